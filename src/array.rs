@@ -0,0 +1,303 @@
+//! Wrappers for fields stored as `TAG_Byte_Array`, `TAG_Int_Array` or `TAG_Long_Array`, and
+//! nibble arrays.
+//!
+//! A plain `Vec<i32>` or `Vec<i64>` is written as a `TAG_List`, these write the array tags. Both
+//! read either tag.
+
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+
+use serde::{
+    de::{self, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{
+    error::{Error, ErrorKind},
+    nbt::{INT_ARRAY_TOKEN, LONG_ARRAY_TOKEN},
+};
+
+/// A `TAG_Byte_Array`. Serde writes a plain `Vec<u8>` as a `TAG_List` of bytes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ByteArray(pub Vec<u8>);
+
+/// A `TAG_Int_Array`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct IntArray(pub Vec<i32>);
+
+/// A `TAG_Long_Array`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct LongArray(pub Vec<i64>);
+
+macro_rules! array {
+    ($array:ident, $elem:ty, $token:expr) => {
+        impl Serialize for $array {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_newtype_struct($token, &self.0)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $array {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                struct ArrayVisitor;
+
+                impl<'de> Visitor<'de> for ArrayVisitor {
+                    type Value = $array;
+
+                    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                        f.write_str(concat!("an array of ", stringify!($elem)))
+                    }
+
+                    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<$array, A::Error> {
+                        let mut values = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+                        while let Some(value) = seq.next_element()? {
+                            values.push(value);
+                        }
+                        Ok($array(values))
+                    }
+
+                    // how `deserialize_any` hands out arrays, see `Value`
+                    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<$array, A::Error> {
+                        match map.next_key::<String>()?.as_deref() {
+                            Some(INT_ARRAY_TOKEN | LONG_ARRAY_TOKEN) => {
+                                map.next_value().map($array)
+                            }
+                            _ => Err(de::Error::invalid_type(de::Unexpected::Map, &self)),
+                        }
+                    }
+                }
+
+                // lists and arrays alike, also when buffered by `#[serde(flatten)]`
+                deserializer.deserialize_any(ArrayVisitor)
+            }
+        }
+
+        impl Deref for $array {
+            type Target = Vec<$elem>;
+
+            fn deref(&self) -> &Vec<$elem> {
+                &self.0
+            }
+        }
+
+        impl DerefMut for $array {
+            fn deref_mut(&mut self) -> &mut Vec<$elem> {
+                &mut self.0
+            }
+        }
+
+        impl From<Vec<$elem>> for $array {
+            fn from(values: Vec<$elem>) -> Self {
+                $array(values)
+            }
+        }
+
+        impl From<$array> for Vec<$elem> {
+            fn from(array: $array) -> Self {
+                array.0
+            }
+        }
+    };
+}
+
+array!(IntArray, i32, INT_ARRAY_TOKEN);
+array!(LongArray, i64, LONG_ARRAY_TOKEN);
+
+impl Deref for ByteArray {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.0
+    }
+}
+
+impl DerefMut for ByteArray {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.0
+    }
+}
+
+impl From<Vec<u8>> for ByteArray {
+    fn from(bytes: Vec<u8>) -> Self {
+        ByteArray(bytes)
+    }
+}
+
+impl From<ByteArray> for Vec<u8> {
+    fn from(array: ByteArray) -> Self {
+        array.0
+    }
+}
+
+impl Serialize for ByteArray {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteArray {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct BytesVisitor;
+
+        impl<'de> Visitor<'de> for BytesVisitor {
+            type Value = ByteArray;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a byte array")
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<ByteArray, E> {
+                Ok(ByteArray(v.to_vec()))
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<ByteArray, E> {
+                Ok(ByteArray(v))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ByteArray, A::Error> {
+                let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+                while let Some(byte) = seq.next_element::<i8>()? {
+                    bytes.push(byte as u8);
+                }
+                Ok(ByteArray(bytes))
+            }
+        }
+
+        deserializer.deserialize_bytes(BytesVisitor)
+    }
+}
+
+/// 4096 values of 4 bits for the blocks of a section, stored as a 2048 byte `TAG_Byte_Array`,
+/// like `BlockLight`, `SkyLight` and the `Data` and `Add` arrays from before 1.13.
+///
+/// Blocks are in YZX order and each byte holds two of them, the first in its low nibble.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct NibbleArray(Box<[u8; NibbleArray::BYTES]>);
+
+impl NibbleArray {
+    /// The length of the byte array.
+    pub const BYTES: usize = 2048;
+
+    /// An array of zeros.
+    pub fn new() -> Self {
+        NibbleArray::filled(0)
+    }
+
+    /// An array holding `value` for every block, e.g. 15 for a section in full sky light.
+    pub fn filled(value: u8) -> Self {
+        assert!(value < 16, "{} doesn't fit into a nibble", value);
+        NibbleArray(Box::new([value << 4 | value; NibbleArray::BYTES]))
+    }
+
+    /// The value of a block, with coordinates within the section between 0 and 15.
+    pub fn get(&self, x: usize, y: usize, z: usize) -> u8 {
+        let index = nibble_index(x, y, z);
+        self.0[index / 2] >> (index % 2 * 4) & 0xf
+    }
+
+    /// Panics if `value` doesn't fit into 4 bits.
+    pub fn set(&mut self, x: usize, y: usize, z: usize, value: u8) {
+        assert!(value < 16, "{} doesn't fit into a nibble", value);
+        let index = nibble_index(x, y, z);
+        let shift = index % 2 * 4;
+        let byte = &mut self.0[index / 2];
+        *byte = *byte & !(0xf << shift) | value << shift;
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0[..]
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0.to_vec()
+    }
+}
+
+fn nibble_index(x: usize, y: usize, z: usize) -> usize {
+    assert!(
+        x < 16 && y < 16 && z < 16,
+        "coordinates outside the section"
+    );
+    (y * 16 + z) * 16 + x
+}
+
+impl Default for NibbleArray {
+    fn default() -> Self {
+        NibbleArray::new()
+    }
+}
+
+impl fmt::Debug for NibbleArray {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("NibbleArray")
+            .field(&self.as_bytes())
+            .finish()
+    }
+}
+
+/// Fails with [`ErrorKind::ListLengthMismatch`] unless there are exactly 2048 bytes.
+impl TryFrom<Vec<u8>> for NibbleArray {
+    type Error = Error;
+
+    fn try_from(bytes: Vec<u8>) -> Result<Self, Error> {
+        let found = bytes.len();
+        let bytes = bytes.into_boxed_slice().try_into().map_err(|_| {
+            Error::from(ErrorKind::ListLengthMismatch {
+                expected: NibbleArray::BYTES,
+                found,
+            })
+        })?;
+
+        Ok(NibbleArray(bytes))
+    }
+}
+
+impl From<NibbleArray> for Vec<u8> {
+    fn from(array: NibbleArray) -> Self {
+        array.into_bytes()
+    }
+}
+
+impl Serialize for NibbleArray {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.as_bytes())
+    }
+}
+
+impl<'de> Deserialize<'de> for NibbleArray {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct NibbleVisitor;
+
+        impl<'de> Visitor<'de> for NibbleVisitor {
+            type Value = NibbleArray;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "a byte array of length {}", NibbleArray::BYTES)
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<NibbleArray, E> {
+                self.visit_byte_buf(v.to_vec())
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<NibbleArray, E> {
+                let len = v.len();
+                NibbleArray::try_from(v).map_err(|_| E::invalid_length(len, &self))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<NibbleArray, A::Error> {
+                let hint = seq.size_hint().unwrap_or(0);
+                let mut bytes = Vec::with_capacity(hint.min(NibbleArray::BYTES));
+                while let Some(byte) = seq.next_element::<i8>()? {
+                    // stop reading as soon as there are too many
+                    if bytes.len() == NibbleArray::BYTES {
+                        return Err(de::Error::invalid_length(bytes.len() + 1, &self));
+                    }
+                    bytes.push(byte as u8);
+                }
+                self.visit_byte_buf(bytes)
+            }
+        }
+
+        deserializer.deserialize_bytes(NibbleVisitor)
+    }
+}
@@ -1,224 +1,607 @@
 use byteorder::{BigEndian, ReadBytesExt};
-use std::io::{Cursor, Read};
+use flate2::bufread::MultiGzDecoder;
+use std::borrow::Cow;
+use std::io::{self, BufRead};
 
 use serde::{
-    de::{MapAccess, SeqAccess},
+    de::{
+        self, value::BorrowedStrDeserializer, IntoDeserializer, MapAccess, SeqAccess, Unexpected,
+    },
     Deserialize,
 };
 
 use crate::{
-    error::Result,
+    error::{ErrorKind, PathSegment, Result},
     nbt::{
-        TAG_BYTE, TAG_BYTE_ARRAY, TAG_COMPOUND, TAG_DOUBLE, TAG_END, TAG_FLOAT, TAG_INT, TAG_LONG,
-        TAG_SHORT, TAG_STRING,
+        INT_ARRAY_TOKEN, LONG_ARRAY_TOKEN, TAG_BYTE, TAG_BYTE_ARRAY, TAG_COMPOUND, TAG_DOUBLE,
+        TAG_END, TAG_FLOAT, TAG_INT, TAG_INT_ARRAY, TAG_LIST, TAG_LONG, TAG_LONG_ARRAY, TAG_SHORT,
+        TAG_STRING,
     },
+    options::{Coercion, Limits, UnsignedPolicy},
+    read::{IoRead, Read, Reference, SliceRead},
     Error,
 };
 
-pub struct Deserializer<R: Read> {
-    reader: R,
-    last_tag: Option<u8>,
-    last_name: Option<String>,
+pub struct Deserializer<R> {
+    pub(crate) reader: R,
+    /// Holds strings and byte arrays that can't be borrowed from the input.
+    scratch: Vec<u8>,
+    /// Tag of the next value, if its header has already been consumed. Values inside a compound
+    /// get their header read by the map access, list elements don't have one at all. Only the
+    /// root value's header is read lazily.
+    pending: Option<u8>,
+    unsigned: UnsignedPolicy,
+    coercion: Coercion,
+    limits: Limits,
+    /// Number of compounds and lists we're currently inside of.
+    depth: usize,
 }
 
-impl<'de, R: Read> Deserializer<R> {
+impl<R: io::Read> Deserializer<IoRead<R>> {
     pub fn from_reader(reader: R) -> Self {
+        Deserializer::new(IoRead::new(reader))
+    }
+
+    /// Returns the underlying reader, positioned after the last value read.
+    pub fn into_inner(self) -> R {
+        self.reader.into_inner()
+    }
+}
+
+impl<'de> Deserializer<SliceRead<'de>> {
+    /// Creates a deserializer that borrows strings and byte arrays from `slice` where possible.
+    pub fn from_slice(slice: &'de [u8]) -> Self {
+        Deserializer::new(SliceRead::new(slice))
+    }
+}
+
+impl<'de, R: Read<'de>> Deserializer<R> {
+    pub fn new(reader: R) -> Self {
         Self {
             reader,
-            last_tag: None,
-            last_name: None,
+            scratch: Vec::new(),
+            pending: None,
+            unsigned: UnsignedPolicy::default(),
+            coercion: Coercion::default(),
+            limits: Limits::default(),
+            depth: 0,
         }
     }
 
-    fn get_last_tag(&mut self) -> std::io::Result<u8> {
-        if self.last_tag.is_none() {
-            self.last_tag = Some(self.reader.read_u8()?);
+    /// Sets how unsigned integers are mapped onto NBT tags, see [`UnsignedPolicy`].
+    pub fn with_unsigned_policy(mut self, policy: UnsignedPolicy) -> Self {
+        self.unsigned = policy;
+        self
+    }
+
+    /// Sets which tags are accepted for numbers and booleans, see [`Coercion`].
+    pub fn with_coercion(mut self, coercion: Coercion) -> Self {
+        self.coercion = coercion;
+        self
+    }
+
+    /// Sets the resource limits for untrusted input, see [`Limits`].
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Fails if reading another `len` bytes would exceed the size limit.
+    pub(crate) fn reserve(&self, len: u64) -> Result<()> {
+        match self.limits.max_bytes {
+            Some(max) if self.reader.offset().saturating_add(len) > max => {
+                Err(ErrorKind::SizeLimitExceeded { max }.into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Enters a compound or list, failing if that exceeds the depth limit.
+    pub(crate) fn enter(&mut self) -> Result<()> {
+        if self.depth >= self.limits.max_depth {
+            return Err(ErrorKind::DepthLimitExceeded {
+                max: self.limits.max_depth,
+            }
+            .into());
         }
+        self.depth += 1;
 
-        Ok(self.last_tag.unwrap())
+        Ok(())
+    }
+
+    pub(crate) fn leave(&mut self) {
+        self.depth -= 1;
     }
 
-    /// Reads the name of the NBT tag from reader but discards it.
-    fn skip_name(&mut self) -> Result<()> {
-        if self.last_name.is_none() {
-            read_name(&mut self.reader)?;
+    /// Reads the tag in front of a named value or the `TAG_End` closing a compound.
+    pub(crate) fn read_tag(&mut self) -> Result<u8> {
+        self.reserve(1)?;
+        Ok(self.reader.read_u8()?)
+    }
+
+    /// Reads `len` bytes, borrowed from the input if possible.
+    pub(crate) fn read_bytes(&mut self, len: u64) -> Result<Reference<'de, '_, [u8]>> {
+        self.reserve(len)?;
+        Ok(self.reader.read_ref(len, &mut self.scratch)?)
+    }
+
+    /// Reads a length-prefixed string, borrowed from the input if possible.
+    pub(crate) fn read_str(&mut self) -> Result<Reference<'de, '_, str>> {
+        self.reserve(2)?;
+        let len = self.reader.read_u16::<BigEndian>()? as u64;
+
+        Ok(match self.read_bytes(len)? {
+            Reference::Borrowed(b) => Reference::Borrowed(std::str::from_utf8(b)?),
+            Reference::Copied(c) => Reference::Copied(std::str::from_utf8(c)?),
+        })
+    }
+
+    pub(crate) fn skip_str(&mut self) -> Result<()> {
+        self.reserve(2)?;
+        let len = self.reader.read_u16::<BigEndian>()?;
+        self.skip_bytes(len as u64)
+    }
+
+    /// Returns the tag of the next value without consuming it.
+    ///
+    /// For the root value this reads the tag and discards its name.
+    fn peek_tag(&mut self) -> Result<u8> {
+        if let Some(tag) = self.pending {
+            return Ok(tag);
+        }
+
+        let tag = self.read_tag()?;
+        if tag != TAG_END {
+            self.skip_str()?;
+        }
+        self.pending = Some(tag);
+
+        Ok(tag)
+    }
+
+    /// Consumes the header of the next value, failing if its tag isn't `expected`.
+    fn expect_tag(&mut self, expected: u8) -> Result<()> {
+        let found = self.peek_tag()?;
+        if found != expected {
+            return Err(Error::unexpected_tag(expected, found));
         }
-        self.last_name = None;
+        self.pending = None;
 
         Ok(())
     }
 
-    fn get_last_name(&mut self) -> Result<&str> {
-        if self.last_name.is_none() {
-            self.last_name = Some(read_name(&mut self.reader)?);
+    /// Reads the next value as an integer and returns it together with the tag it was stored in.
+    ///
+    /// In strict mode the tag has to be `expected`, in lenient mode any integer tag is accepted.
+    fn read_integer(&mut self, expected: u8) -> Result<(u8, i64)> {
+        let found = self.peek_tag()?;
+        let accepted = match self.coercion {
+            Coercion::Strict => found == expected,
+            Coercion::Lenient => matches!(found, TAG_BYTE | TAG_SHORT | TAG_INT | TAG_LONG),
+        };
+        if !accepted {
+            return Err(Error::unexpected_tag(expected, found));
+        }
+        self.pending = None;
+
+        self.reserve(scalar_size(found))?;
+        let v = match found {
+            TAG_BYTE => self.reader.read_i8()? as i64,
+            TAG_SHORT => self.reader.read_i16::<BigEndian>()? as i64,
+            TAG_INT => self.reader.read_i32::<BigEndian>()? as i64,
+            _ => self.reader.read_i64::<BigEndian>()?,
+        };
+        Ok((found, v))
+    }
+
+    /// Reads the next value as a floating point number.
+    ///
+    /// In strict mode the tag has to be `expected`, in lenient mode both float tags are accepted.
+    fn read_float(&mut self, expected: u8) -> Result<f64> {
+        let found = self.peek_tag()?;
+        let accepted = match self.coercion {
+            Coercion::Strict => found == expected,
+            Coercion::Lenient => matches!(found, TAG_FLOAT | TAG_DOUBLE),
+        };
+        if !accepted {
+            return Err(Error::unexpected_tag(expected, found));
+        }
+        self.pending = None;
+
+        self.reserve(scalar_size(found))?;
+        match found {
+            TAG_FLOAT => Ok(self.reader.read_f32::<BigEndian>()? as f64),
+            _ => Ok(self.reader.read_f64::<BigEndian>()?),
+        }
+    }
+
+    /// Skips the payload of a value with the given tag.
+    pub(crate) fn skip_payload(&mut self, tag: u8) -> Result<()> {
+        match tag {
+            TAG_END => {}
+            TAG_BYTE | TAG_SHORT | TAG_INT | TAG_LONG | TAG_FLOAT | TAG_DOUBLE => {
+                self.skip_bytes(scalar_size(tag))?
+            }
+            TAG_BYTE_ARRAY => {
+                let len = self.read_len()?;
+                self.skip_bytes(len)?;
+            }
+            TAG_INT_ARRAY => {
+                let len = self.read_len()?;
+                self.skip_bytes(len * 4)?;
+            }
+            TAG_LONG_ARRAY => {
+                let len = self.read_len()?;
+                self.skip_bytes(len * 8)?;
+            }
+            TAG_STRING => self.skip_str()?,
+            TAG_LIST => {
+                self.enter()?;
+                self.reserve(5)?;
+                let elem = self.reader.read_u8()?;
+                let len = self.read_len()?;
+                for _ in 0..len {
+                    self.skip_payload(elem)?;
+                }
+                self.leave();
+            }
+            TAG_COMPOUND => {
+                self.enter()?;
+                loop {
+                    let tag = self.read_tag()?;
+                    if tag == TAG_END {
+                        break;
+                    }
+                    self.skip_str()?;
+                    self.skip_payload(tag)?;
+                }
+                self.leave();
+            }
+            other => return Err(ErrorKind::UnknownTag(other).into()),
         }
 
-        Ok(&self.last_name.as_ref().unwrap())
+        Ok(())
+    }
+
+    pub(crate) fn skip_bytes(&mut self, len: u64) -> Result<()> {
+        self.reserve(len)?;
+        Ok(self.reader.skip(len)?)
+    }
+
+    /// Reads the length prefix of an array or list, negative lengths count as empty.
+    pub(crate) fn read_len(&mut self) -> Result<u64> {
+        self.reserve(4)?;
+        let len = self.reader.read_i32::<BigEndian>()?.max(0) as u64;
+        match self.limits.max_len {
+            Some(max) if len > max => Err(ErrorKind::LengthLimitExceeded { len, max }.into()),
+            _ => Ok(len),
+        }
     }
+
+    /// Moves to the value at `path` below the root, skipping everything before it. Afterwards
+    /// the header of that value has been consumed.
+    fn descend(&mut self, path: &[PathSegment]) -> Result<()> {
+        let mut tag = self.peek_tag()?;
+        for (i, segment) in path.iter().enumerate() {
+            let not_found = || {
+                path[..=i]
+                    .iter()
+                    .rev()
+                    .fold(Error::from(ErrorKind::NotFound), |e, s| e.at(s.clone()))
+            };
+
+            self.pending = None;
+            tag = match segment {
+                PathSegment::Key(key) => {
+                    if tag != TAG_COMPOUND {
+                        return Err(Error::unexpected_tag(TAG_COMPOUND, tag));
+                    }
+                    self.enter()?;
+                    loop {
+                        let found = self.read_tag()?;
+                        if found == TAG_END {
+                            return Err(not_found());
+                        }
+                        if *self.read_str()? == **key {
+                            break found;
+                        }
+                        self.skip_payload(found)?;
+                    }
+                }
+                PathSegment::Index(index) => {
+                    if tag != TAG_LIST {
+                        return Err(Error::unexpected_tag(TAG_LIST, tag));
+                    }
+                    self.enter()?;
+                    self.reserve(1)?;
+                    let elem = self.reader.read_u8()?;
+                    let len = self.read_len()?;
+                    if *index as u64 >= len {
+                        return Err(not_found());
+                    }
+                    for _ in 0..*index {
+                        self.skip_payload(elem)?;
+                    }
+                    elem
+                }
+            };
+            self.pending = Some(tag);
+        }
+
+        Ok(())
+    }
+
+    /// Deserializes a list or array whose header has been consumed already.
+    fn visit_list<V>(&mut self, tag: u8, visitor: V) -> Result<V::Value>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        let len = self.read_len()?;
+        let mut access = ListAccess {
+            de: self,
+            tag,
+            remaining: len,
+            index: 0,
+        };
+        let value = visitor.visit_seq(&mut access)?;
+        access.finish()?;
+
+        Ok(value)
+    }
+}
+
+/// Deserializes a value from `reader`.
+///
+/// The reader is taken by value, so owned readers like `BufReader<File>` or gzip decoders can be
+/// passed directly, while `&mut R` works for readers that are used again afterwards. Nothing is
+/// borrowed from the input; use [`from_slice`] for types that borrow strings or byte arrays.
+pub fn from_reader<R: io::Read, T: serde::de::DeserializeOwned>(reader: R) -> Result<T> {
+    let mut deserializer = Deserializer::from_reader(reader);
+
+    T::deserialize(&mut deserializer).map_err(|e| e.with_offset(deserializer.reader.offset()))
+}
+
+/// Like [`from_reader`], but gunzips the input first if it starts with the gzip magic, as
+/// `level.dat` and player files do. Uncompressed input is read as is.
+pub fn from_gzip_reader<R: io::Read, T: serde::de::DeserializeOwned>(reader: R) -> Result<T> {
+    from_reader(gunzip(reader)?)
+}
+
+/// `reader`, decompressed if it starts with the gzip magic.
+pub(crate) fn gunzip<'a, R: io::Read + 'a>(reader: R) -> io::Result<Box<dyn io::Read + 'a>> {
+    let mut reader = io::BufReader::new(reader);
+    if reader.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        Ok(Box::new(MultiGzDecoder::new(reader)))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Deserializes a value from `bytes`, borrowing strings and byte arrays from it where the
+/// target type allows, e.g. for `&'de str`, `Cow<'de, str>` or `&'de [u8]` fields.
+pub fn from_slice<'de, T: serde::de::Deserialize<'de>>(bytes: &'de [u8]) -> Result<T> {
+    let mut deserializer = Deserializer::from_slice(bytes);
+
+    T::deserialize(&mut deserializer).map_err(|e| e.with_offset(deserializer.reader.offset()))
+}
+
+pub fn from_bytes<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    from_slice(bytes)
 }
 
-pub fn from_reader<'de, R: Read, T: serde::de::Deserialize<'de>>(reader: &'de mut R) -> Result<T> {
+/// Deserializes only the value at `path` below the root, e.g. `Data.Player` or
+/// `Level.Sections[3].BlockStates`. Everything in front of it is skipped without being decoded.
+///
+/// The path consists of compound keys separated by dots, each followed by any number of list
+/// indices in brackets.
+pub fn from_reader_at_path<R: io::Read, T: serde::de::DeserializeOwned>(
+    reader: R,
+    path: &str,
+) -> Result<T> {
+    let path = parse_path(path)?;
     let mut deserializer = Deserializer::from_reader(reader);
 
-    T::deserialize(&mut deserializer)
+    deserializer
+        .descend(&path)
+        .and_then(|()| {
+            T::deserialize(&mut deserializer)
+                .map_err(|e| path.iter().rev().fold(e, |e, s| e.at(s.clone())))
+        })
+        .map_err(|e| e.with_offset(deserializer.reader.offset()))
 }
 
-pub fn from_bytes<'de, T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T> {
-    from_reader(&mut Cursor::new(bytes))
+/// Splits a path like `Level.Sections[3].BlockStates` into its segments.
+fn parse_path(path: &str) -> Result<Vec<PathSegment>> {
+    let mut segments = Vec::new();
+    if path.is_empty() {
+        return Ok(segments);
+    }
+
+    for part in path.split('.') {
+        let invalid = || Error::from(ErrorKind::InvalidPath(part.to_string()));
+
+        let (key, mut indices) = part.split_at(part.find('[').unwrap_or(part.len()));
+        if key.is_empty() && (indices.is_empty() || segments.is_empty()) {
+            return Err(invalid());
+        }
+        if !key.is_empty() {
+            segments.push(PathSegment::Key(key.to_string()));
+        }
+        while !indices.is_empty() {
+            let end = indices.find(']').ok_or_else(invalid)?;
+            let index = indices[1..end].parse().map_err(|_| invalid())?;
+            segments.push(PathSegment::Index(index));
+            indices = &indices[end + 1..];
+            if !indices.is_empty() && !indices.starts_with('[') {
+                return Err(invalid());
+            }
+        }
+    }
+
+    Ok(segments)
 }
 
-impl<'de, 'a, R: Read> serde::Deserializer<'de> for &'a mut Deserializer<R> {
+impl<'de, R: Read<'de>> serde::Deserializer<'de> for &mut Deserializer<R> {
     type Error = Error;
 
     fn deserialize_any<V>(self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        todo!()
+        match self.peek_tag()? {
+            TAG_BYTE => visitor.visit_i8(self.read_integer(TAG_BYTE)?.1 as i8),
+            TAG_SHORT => visitor.visit_i16(self.read_integer(TAG_SHORT)?.1 as i16),
+            TAG_INT => visitor.visit_i32(self.read_integer(TAG_INT)?.1 as i32),
+            TAG_LONG => visitor.visit_i64(self.read_integer(TAG_LONG)?.1),
+            TAG_FLOAT => visitor.visit_f32(self.read_float(TAG_FLOAT)? as f32),
+            TAG_DOUBLE => visitor.visit_f64(self.read_float(TAG_DOUBLE)?),
+            TAG_STRING => self.deserialize_str(visitor),
+            TAG_BYTE_ARRAY => self.deserialize_bytes(visitor),
+            TAG_LIST => self.deserialize_seq(visitor),
+            TAG_COMPOUND => self.deserialize_map(visitor),
+            TAG_INT_ARRAY => visitor.visit_map(ArrayAccess {
+                de: self,
+                token: Some(INT_ARRAY_TOKEN),
+            }),
+            TAG_LONG_ARRAY => visitor.visit_map(ArrayAccess {
+                de: self,
+                token: Some(LONG_ARRAY_TOKEN),
+            }),
+            found => Err(Error::unexpected_tag(TAG_COMPOUND, found)),
+        }
     }
 
-    fn deserialize_bool<V>(mut self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
+    fn deserialize_bool<V>(self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        if self.get_last_tag()? != TAG_BYTE {
-            return Err(Error::ExpectedByte);
+        match self.coercion {
+            Coercion::Strict => {
+                self.expect_tag(TAG_BYTE)?;
+                self.reserve(1)?;
+                visitor.visit_bool(self.reader.read_u8()? == 1)
+            }
+            Coercion::Lenient => match self.read_integer(TAG_BYTE)?.1 {
+                0 => visitor.visit_bool(false),
+                1 => visitor.visit_bool(true),
+                v => Err(de::Error::invalid_value(Unexpected::Signed(v), &"0 or 1")),
+            },
         }
-
-        self.skip_name()?;
-        self.last_tag = None;
-
-        visitor.visit_bool(self.reader.read_u8()? == 1)
     }
 
-    fn deserialize_i8<V>(mut self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
+    fn deserialize_i8<V>(self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        if self.get_last_tag()? != TAG_SHORT {
-            return Err(Error::ExpectedShort);
-        }
-        self.skip_name()?;
-        self.last_tag = None;
-
-        visitor.visit_i8(i8::try_from(self.reader.read_i16::<BigEndian>()?)?)
+        let (_, v) = self.read_integer(TAG_BYTE)?;
+        visitor.visit_i8(i8::try_from(v)?)
     }
 
-    fn deserialize_i16<V>(mut self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
+    fn deserialize_i16<V>(self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        if self.get_last_tag()? != TAG_SHORT {
-            return Err(Error::ExpectedShort);
-        }
-        self.skip_name()?;
-        self.last_tag = None;
-
-        visitor.visit_i16(self.reader.read_i16::<BigEndian>()?)
+        let (_, v) = self.read_integer(TAG_SHORT)?;
+        visitor.visit_i16(i16::try_from(v)?)
     }
 
-    fn deserialize_i32<V>(mut self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
+    fn deserialize_i32<V>(self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        if self.get_last_tag()? != TAG_INT {
-            return Err(Error::ExpectedShort);
-        }
-        self.skip_name()?;
-        self.last_tag = None;
-
-        visitor.visit_i32(self.reader.read_i32::<BigEndian>()?)
+        let (_, v) = self.read_integer(TAG_INT)?;
+        visitor.visit_i32(i32::try_from(v)?)
     }
 
-    fn deserialize_i64<V>(mut self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
+    fn deserialize_i64<V>(self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        if self.get_last_tag()? != TAG_LONG {
-            return Err(Error::ExpectedLong);
-        }
-        self.skip_name()?;
-        self.last_tag = None;
-
-        visitor.visit_i64(self.reader.read_i64::<BigEndian>()?)
+        let (_, v) = self.read_integer(TAG_LONG)?;
+        visitor.visit_i64(v)
     }
 
-    fn deserialize_u8<V>(mut self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
+    fn deserialize_u8<V>(self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        if self.get_last_tag()? != TAG_BYTE {
-            return Err(Error::ExpectedByte);
+        match self.unsigned {
+            // always a byte, see `UnsignedPolicy::Widen`
+            UnsignedPolicy::Widen | UnsignedPolicy::Reinterpret => {
+                match self.read_integer(TAG_BYTE)? {
+                    (TAG_BYTE, v) => visitor.visit_u8(v as u8),
+                    (_, v) => visitor.visit_u8(u8::try_from(v)?),
+                }
+            }
+            UnsignedPolicy::Reject => Err(ErrorKind::UnsignedRejected.into()),
         }
-        self.skip_name()?;
-        self.last_tag = None;
-
-        visitor.visit_u8(self.reader.read_u8()?)
     }
 
-    fn deserialize_u16<V>(mut self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
+    fn deserialize_u16<V>(self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        if self.get_last_tag()? != TAG_INT {
-            return Err(Error::ExpectedShort);
+        match self.unsigned {
+            UnsignedPolicy::Widen => {
+                let (_, v) = self.read_integer(TAG_INT)?;
+                visitor.visit_u16(u16::try_from(v)?)
+            }
+            UnsignedPolicy::Reinterpret => match self.read_integer(TAG_SHORT)? {
+                (TAG_SHORT, v) => visitor.visit_u16(v as u16),
+                (_, v) => visitor.visit_u16(u16::try_from(v)?),
+            },
+            UnsignedPolicy::Reject => Err(ErrorKind::UnsignedRejected.into()),
         }
-        self.skip_name()?;
-        self.last_tag = None;
-
-        visitor.visit_i32(self.reader.read_i32::<BigEndian>()?)
     }
 
-    fn deserialize_u32<V>(mut self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
+    fn deserialize_u32<V>(self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        if self.get_last_tag()? != TAG_LONG {
-            return Err(Error::ExpectedShort);
+        match self.unsigned {
+            UnsignedPolicy::Widen => {
+                let (_, v) = self.read_integer(TAG_LONG)?;
+                visitor.visit_u32(u32::try_from(v)?)
+            }
+            UnsignedPolicy::Reinterpret => match self.read_integer(TAG_INT)? {
+                (TAG_INT, v) => visitor.visit_u32(v as u32),
+                (_, v) => visitor.visit_u32(u32::try_from(v)?),
+            },
+            UnsignedPolicy::Reject => Err(ErrorKind::UnsignedRejected.into()),
         }
-        self.skip_name()?;
-        self.last_tag = None;
-
-        visitor.visit_i64(self.reader.read_i64::<BigEndian>()?)
     }
 
-    fn deserialize_u64<V>(mut self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
+    fn deserialize_u64<V>(self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        if self.get_last_tag()? != TAG_LONG {
-            return Err(Error::ExpectedShort);
+        match self.unsigned {
+            UnsignedPolicy::Widen => {
+                let (_, v) = self.read_integer(TAG_LONG)?;
+                visitor.visit_u64(u64::try_from(v)?)
+            }
+            UnsignedPolicy::Reinterpret => match self.read_integer(TAG_LONG)? {
+                (TAG_LONG, v) => visitor.visit_u64(v as u64),
+                (_, v) => visitor.visit_u64(u64::try_from(v)?),
+            },
+            UnsignedPolicy::Reject => Err(ErrorKind::UnsignedRejected.into()),
         }
-        self.skip_name()?;
-        self.last_tag = None;
-
-        visitor.visit_i64(i64::try_from(self.reader.read_u64::<BigEndian>()?)?)
     }
 
-    fn deserialize_f32<V>(mut self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
+    fn deserialize_f32<V>(self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        if self.get_last_tag()? != TAG_FLOAT {
-            return Err(Error::ExpectedShort);
-        }
-        self.skip_name()?;
-        self.last_tag = None;
-
-        visitor.visit_f32(self.reader.read_f32::<BigEndian>()?)
+        let v = self.read_float(TAG_FLOAT)?;
+        visitor.visit_f32(v as f32)
     }
 
-    fn deserialize_f64<V>(mut self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
+    fn deserialize_f64<V>(self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        if self.get_last_tag()? != TAG_DOUBLE {
-            return Err(Error::ExpectedShort);
-        }
-        self.skip_name()?;
-        self.last_tag = None;
-
-        visitor.visit_f64(self.reader.read_f64::<BigEndian>()?)
+        let v = self.read_float(TAG_DOUBLE)?;
+        visitor.visit_f64(v)
     }
 
     fn deserialize_char<V>(mut self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
@@ -232,21 +615,12 @@ impl<'de, 'a, R: Read> serde::Deserializer<'de> for &'a mut Deserializer<R> {
     where
         V: serde::de::Visitor<'de>,
     {
-        println!("deserializing string");
-        if self.get_last_tag()? != TAG_STRING {
-            return Err(Error::ExpectedShort);
-        }
-        self.skip_name()?;
-        self.last_tag = None;
+        self.expect_tag(TAG_STRING)?;
 
-        let len = self.reader.read_i16::<BigEndian>()?;
-        let mut buf = vec![0; len as usize];
-        self.reader.read(&mut buf)?;
-        let string = String::from_utf8(buf)?;
-
-        println!("string: {}, len: {}", string, len);
-
-        visitor.visit_str(&string)
+        match self.read_str()? {
+            Reference::Borrowed(s) => visitor.visit_borrowed_str(s),
+            Reference::Copied(s) => visitor.visit_str(s),
+        }
     }
 
     fn deserialize_string<V>(self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
@@ -260,31 +634,29 @@ impl<'de, 'a, R: Read> serde::Deserializer<'de> for &'a mut Deserializer<R> {
     where
         V: serde::de::Visitor<'de>,
     {
-        if self.get_last_tag()? != TAG_BYTE_ARRAY {
-            return Err(Error::ExpectedByteArray);
-        }
-        self.skip_name()?;
-        self.last_tag = None;
+        self.expect_tag(TAG_BYTE_ARRAY)?;
 
-        let len = self.reader.read_i16::<BigEndian>()?;
-        let mut buf = vec![0; len as usize];
-        self.reader.read(&mut buf)?;
+        let len = self.read_len()?;
 
-        visitor.visit_bytes(&buf)
+        match self.read_bytes(len)? {
+            Reference::Borrowed(b) => visitor.visit_borrowed_bytes(b),
+            Reference::Copied(b) => visitor.visit_bytes(b),
+        }
     }
 
     fn deserialize_byte_buf<V>(self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        todo!()
+        self.deserialize_bytes(visitor)
     }
 
     fn deserialize_option<V>(self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        todo!()
+        // NBT has no null, a missing field is how `None` is stored
+        visitor.visit_some(self)
     }
 
     fn deserialize_unit<V>(self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
@@ -307,82 +679,91 @@ impl<'de, 'a, R: Read> serde::Deserializer<'de> for &'a mut Deserializer<R> {
 
     fn deserialize_newtype_struct<V>(
         self,
-        name: &'static str,
+        _name: &'static str,
         visitor: V,
     ) -> std::prelude::v1::Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_newtype_struct(self)
     }
 
     fn deserialize_seq<V>(self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        todo!()
+        let elem = match self.peek_tag()? {
+            TAG_LIST => {
+                self.pending = None;
+                self.reserve(1)?;
+                self.reader.read_u8()?
+            }
+            TAG_BYTE_ARRAY => TAG_BYTE,
+            TAG_INT_ARRAY => TAG_INT,
+            TAG_LONG_ARRAY => TAG_LONG,
+            found => return Err(Error::unexpected_tag(TAG_LIST, found)),
+        };
+        self.pending = None;
+
+        self.enter()?;
+        let value = self.visit_list(elem, visitor)?;
+        self.leave();
+
+        Ok(value)
     }
 
     fn deserialize_tuple<V>(
         self,
-        len: usize,
+        _len: usize,
         visitor: V,
     ) -> std::prelude::v1::Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        todo!()
+        self.deserialize_seq(visitor)
     }
 
     fn deserialize_tuple_struct<V>(
         self,
-        name: &'static str,
-        len: usize,
+        _name: &'static str,
+        _len: usize,
         visitor: V,
     ) -> std::prelude::v1::Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        todo!()
+        self.deserialize_seq(visitor)
     }
 
     fn deserialize_map<V>(self, visitor: V) -> std::prelude::v1::Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        todo!()
+        self.expect_tag(TAG_COMPOUND)?;
+        self.enter()?;
+
+        let mut access = CompoundAccess {
+            de: &mut *self,
+            key: Cow::Borrowed(""),
+            done: false,
+        };
+        let value = visitor.visit_map(&mut access)?;
+        access.finish()?;
+        self.leave();
+
+        Ok(value)
     }
 
     fn deserialize_struct<V>(
         self,
-        name: &'static str,
-        fields: &'static [&'static str],
+        _name: &'static str,
+        _fields: &'static [&'static str],
         visitor: V,
     ) -> std::prelude::v1::Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        println!("try deserializing struct");
-        if self.get_last_tag()? != TAG_COMPOUND {
-            return Err(Error::ExpectedCompound);
-        }
-        println!("nbt tag is compound");
-        self.skip_name()?;
-
-        println!("nbt name is {}", name);
-        self.last_tag = None;
-
-        visitor.visit_map(&mut *self)
-
-        /*
-        println!("end of struct");
-        let end = self.reader.read_u8()?;
-
-        if end != TAG_END {
-            return Err(Error::ExpectedEnd);
-        }
-        Ok(val)
-            */
+        self.deserialize_map(visitor)
     }
 
     fn deserialize_enum<V>(
@@ -404,12 +785,7 @@ impl<'de, 'a, R: Read> serde::Deserializer<'de> for &'a mut Deserializer<R> {
     where
         V: serde::de::Visitor<'de>,
     {
-        if let Some(name) = self.last_name.as_ref() {
-            println!("identifier: {}", name);
-            return visitor.visit_str(name);
-        }
-
-        return Err(Error::ExpectedIdentifier);
+        self.deserialize_str(visitor)
     }
 
     fn deserialize_ignored_any<V>(
@@ -419,34 +795,92 @@ impl<'de, 'a, R: Read> serde::Deserializer<'de> for &'a mut Deserializer<R> {
     where
         V: serde::de::Visitor<'de>,
     {
-        println!("{:?}", self.last_name);
+        let tag = self.peek_tag()?;
+        trace_event!(tag = crate::nbt::tag_name(tag), "skipping ignored value");
 
-        self.reader.read_u8()?;
+        self.pending = None;
+        self.skip_payload(tag)?;
         visitor.visit_unit()
     }
 }
 
-impl<'de, R: Read> SeqAccess<'de> for Deserializer<R> {
+/// Hands an int or long array to `deserialize_any` visitors as a map from the array's token to
+/// its elements, so it can be told apart from a list.
+struct ArrayAccess<'a, R> {
+    de: &'a mut Deserializer<R>,
+    token: Option<&'static str>,
+}
+
+impl<'de, R: Read<'de>> MapAccess<'de> for ArrayAccess<'_, R> {
     type Error = Error;
 
-    fn next_element_seed<T>(
-        &mut self,
-        seed: T,
-    ) -> std::prelude::v1::Result<Option<T::Value>, Self::Error>
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
     where
-        T: serde::de::DeserializeSeed<'de>,
+        K: de::DeserializeSeed<'de>,
     {
-        let tag = self.get_last_tag()?;
+        match self.token.take() {
+            Some(token) => seed
+                .deserialize(BorrowedStrDeserializer::new(token))
+                .map(Some),
+            None => Ok(None),
+        }
+    }
 
-        if tag == TAG_END {
-            return Ok(None);
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(PendingArray(&mut *self.de))
+    }
+}
+
+/// The elements of an [`ArrayAccess`]. The array's tag is still pending, so it is read as a
+/// sequence whatever the visitor asks for, instead of going through `deserialize_any` again.
+struct PendingArray<'a, R>(&'a mut Deserializer<R>);
+
+impl<'de, R: Read<'de>> serde::Deserializer<'de> for PendingArray<'_, R> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.0.deserialize_seq(visitor)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map struct enum identifier
+        ignored_any
+    }
+}
+
+/// Gives access to the entries of a compound whose header has been consumed.
+struct CompoundAccess<'a, 'de, R> {
+    de: &'a mut Deserializer<R>,
+    /// Name of the current entry, kept around to report it in errors.
+    key: Cow<'de, str>,
+    done: bool,
+}
+
+impl<'de, R: Read<'de>> CompoundAccess<'_, 'de, R> {
+    /// Skips the entries the visitor didn't ask for and the closing `TAG_End`.
+    fn finish(&mut self) -> Result<()> {
+        while !self.done {
+            let tag = self.de.read_tag()?;
+            if tag == TAG_END {
+                self.done = true;
+            } else {
+                self.de.skip_str()?;
+                self.de.skip_payload(tag)?;
+            }
         }
 
-        seed.deserialize(self).map(Some)
+        Ok(())
     }
 }
 
-impl<'de, R: Read> MapAccess<'de> for Deserializer<R> {
+impl<'de, R: Read<'de>> MapAccess<'de> for CompoundAccess<'_, 'de, R> {
     type Error = Error;
 
     fn next_key_seed<K>(
@@ -456,30 +890,103 @@ impl<'de, R: Read> MapAccess<'de> for Deserializer<R> {
     where
         K: serde::de::DeserializeSeed<'de>,
     {
-        let tag = self.get_last_tag()?;
+        if self.done {
+            return Ok(None);
+        }
+
+        let tag = self.de.read_tag()?;
         if tag == TAG_END {
+            self.done = true;
             return Ok(None);
         }
-        let name = self.get_last_name()?;
+        self.key = match self.de.read_str()? {
+            Reference::Borrowed(key) => Cow::Borrowed(key),
+            Reference::Copied(key) => Cow::Owned(key.to_owned()),
+        };
+        self.de.pending = Some(tag);
+
+        match &self.key {
+            Cow::Borrowed(key) => seed.deserialize(BorrowedStrDeserializer::new(key)),
+            Cow::Owned(key) => seed.deserialize(key.as_str().into_deserializer()),
+        }
+        .map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> std::prelude::v1::Result<V::Value, Self::Error>
+    where
+        V: serde::de::DeserializeSeed<'de>,
+    {
+        trace_span!(
+            "nbt_field",
+            name = &*self.key,
+            tag = self.de.pending.map(crate::nbt::tag_name)
+        );
+
+        seed.deserialize(&mut *self.de).map_err(|e| {
+            e.with_offset(self.de.reader.offset())
+                .at(PathSegment::Key(std::mem::take(&mut self.key).into_owned()))
+        })
+    }
+}
 
-        println!("tag: {}, name: {}", tag, name);
+/// Gives access to the elements of a list or array whose length has been consumed.
+struct ListAccess<'a, R> {
+    de: &'a mut Deserializer<R>,
+    tag: u8,
+    remaining: u64,
+    index: usize,
+}
 
-        println!("type of seed: {}", std::any::type_name::<K::Value>());
+impl<'de, R: Read<'de>> ListAccess<'_, R> {
+    /// Skips the elements the visitor didn't ask for.
+    fn finish(&mut self) -> Result<()> {
+        while self.remaining > 0 {
+            self.remaining -= 1;
+            self.de.skip_payload(self.tag)?;
+        }
 
-        seed.deserialize(self).map(Some)
+        Ok(())
     }
+}
 
-    fn next_value_seed<V>(&mut self, seed: V) -> std::prelude::v1::Result<V::Value, Self::Error>
+impl<'de, R: Read<'de>> SeqAccess<'de> for ListAccess<'_, R> {
+    type Error = Error;
+
+    fn next_element_seed<T>(
+        &mut self,
+        seed: T,
+    ) -> std::prelude::v1::Result<Option<T::Value>, Self::Error>
     where
-        V: serde::de::DeserializeSeed<'de>,
+        T: serde::de::DeserializeSeed<'de>,
     {
-        seed.deserialize(self)
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        self.de.pending = Some(self.tag);
+
+        let index = self.index;
+        self.index += 1;
+        trace_span!("nbt_element", index, tag = crate::nbt::tag_name(self.tag));
+
+        seed.deserialize(&mut *self.de).map(Some).map_err(|e| {
+            e.with_offset(self.de.reader.offset())
+                .at(PathSegment::Index(index))
+        })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        usize::try_from(self.remaining).ok()
     }
 }
 
-pub fn read_name<R: std::io::Read>(r: &mut R) -> Result<String> {
-    let len = r.read_u16::<BigEndian>()?;
-    let mut buf = vec![0; len as usize];
-    r.read(&mut buf)?;
-    Ok(String::from_utf8(buf)?)
+/// Size of the payload of a numeric tag, 0 for all other tags.
+fn scalar_size(tag: u8) -> u64 {
+    match tag {
+        TAG_BYTE => 1,
+        TAG_SHORT => 2,
+        TAG_INT | TAG_FLOAT => 4,
+        TAG_LONG | TAG_DOUBLE => 8,
+        _ => 0,
+    }
 }
@@ -1,65 +1,332 @@
+use std::fmt;
 use std::num::TryFromIntError;
 
-use rnbt::NbtError;
 use serde::{de, ser};
 
+use crate::nbt::Tag;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// An error that occurred while writing or reading NBT.
+///
+/// Besides the [`ErrorKind`], errors raised while reading carry the path of the value that
+/// failed (e.g. `Level.Sections[3].BlockStates`) and the offset into the stream at which the
+/// failure was noticed.
+pub struct Error {
+    inner: Box<ErrorImpl>,
+}
+
+struct ErrorImpl {
+    kind: ErrorKind,
+    path: Vec<PathSegment>,
+    offset: Option<u64>,
+}
+
+/// What went wrong, see [`Error::kind`].
 #[derive(Debug)]
-pub enum Error {
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// A custom message, usually raised by a `Serialize` or `Deserialize` implementation.
     Message(String),
-    IoError(std::io::Error),
-    Uft8Error(std::string::FromUtf8Error),
-    IntError(TryFromIntError),
-    IncompatibleListType,
-    UnknownListType,
-    NotWritingToList,
-    NotWritingToCompound,
-    ExpectedByte,
-    ExpectedShort,
-    ExpectedByteArray,
-    ExpectedLong,
-    ExpectedCompound,
-    ExpectedStruct(String),
-    ExpectedIdentifier,
+    Io(std::io::Error),
+    Utf8(std::str::Utf8Error),
+    /// An integer didn't fit into the type it was written as or read into.
+    IntOutOfRange(TryFromIntError),
+    /// A value was stored with a different tag than the one requested.
+    UnexpectedTag {
+        expected: Tag,
+        found: Tag,
+    },
+    /// A tag id outside of the known range was read.
+    UnknownTag(u8),
+    /// A list element was written with a different tag than the previous elements.
+    IncompatibleListType {
+        expected: Tag,
+        found: Tag,
+    },
+    /// A list announced a different number of elements than were written.
+    ListLengthMismatch {
+        expected: usize,
+        found: usize,
+    },
+    /// A compound wasn't terminated by `TAG_End`, or an [`NbtWriter`] was finished with a
+    /// compound or list still open.
+    ///
+    /// [`NbtWriter`]: crate::NbtWriter
     ExpectedEnd,
+    /// An [`NbtWriter`] was asked to close a `found` while `open` is the innermost open value.
+    ///
+    /// [`NbtWriter`]: crate::NbtWriter
+    UnbalancedEnd {
+        open: Option<Tag>,
+        found: Tag,
+    },
+    /// Compounds and lists were nested deeper than [`Limits::max_depth`].
+    ///
+    /// [`Limits::max_depth`]: crate::Limits::max_depth
+    DepthLimitExceeded {
+        max: usize,
+    },
+    /// Reading the next value would exceed [`Limits::max_bytes`].
+    ///
+    /// [`Limits::max_bytes`]: crate::Limits::max_bytes
+    SizeLimitExceeded {
+        max: u64,
+    },
+    /// A list or array is longer than [`Limits::max_len`].
+    ///
+    /// [`Limits::max_len`]: crate::Limits::max_len
+    LengthLimitExceeded {
+        len: u64,
+        max: u64,
+    },
+    /// A map key was neither a string nor an integer.
+    KeyMustBeAString,
+    /// There is no value at the requested path, see [`Error::path`].
+    NotFound,
+    /// Nothing matched an [`NbtPath`] up to and including the given part of it.
+    ///
+    /// [`NbtPath`]: crate::NbtPath
+    NoMatch(String),
+    /// A path segment couldn't be parsed.
+    InvalidPath(String),
+    /// An unsigned integer was written or read under [`UnsignedPolicy::Reject`].
+    ///
+    /// [`UnsignedPolicy::Reject`]: crate::UnsignedPolicy::Reject
+    UnsignedRejected,
+    /// A region file chunk uses a compression type this crate doesn't know.
+    UnknownCompression(u8),
+    /// A region file chunk is compressed with the named custom algorithm.
+    UnsupportedCompression(String),
+    /// A region file chunk is stored in an external `.mcc` file, but the region wasn't opened
+    /// from a path to find it next to.
+    UnknownRegionPath,
+    /// A region file chunk's length is zero or larger than the sectors allocated to it.
+    InvalidChunkLength(u32),
+    /// A compressed chunk of this many bytes doesn't fit into the 255 sectors a region file can
+    /// give it, and the region wasn't opened from a path to store it next to in a `.mcc` file.
+    ChunkTooLarge(usize),
+    /// A chunk was written by a game version older than the chunk model supports, with this
+    /// `DataVersion`, or 0 if it has none.
+    UnsupportedChunkVersion(i32),
+    /// A block state string isn't of the form `name[property=value,...]`.
+    InvalidBlockState(String),
+    /// A schematic's block data doesn't match its size or palette.
+    InvalidSchematic(String),
+    /// A schematic file has a format version this crate can't read.
+    UnsupportedSchematicVersion(i32),
+    /// A block position is negative or too large for a structure's size.
+    OutOfBounds([i32; 3]),
+}
+
+/// One step in the path of a value inside an NBT tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    /// A named entry of a compound.
+    Key(String),
+    /// An element of a list or array.
+    Index(usize),
+}
+
+impl Error {
+    pub fn kind(&self) -> &ErrorKind {
+        &self.inner.kind
+    }
+
+    /// The path from the root to the value that failed, empty if the error isn't tied to a
+    /// value inside the root compound.
+    pub fn path(&self) -> &[PathSegment] {
+        &self.inner.path
+    }
+
+    /// The number of bytes consumed from the stream when the error was noticed.
+    pub fn offset(&self) -> Option<u64> {
+        self.inner.offset
+    }
+
+    pub(crate) fn unexpected_tag(expected: u8, found: u8) -> Self {
+        match (Tag::try_from(expected), Tag::try_from(found)) {
+            (Ok(expected), Ok(found)) => ErrorKind::UnexpectedTag { expected, found }.into(),
+            (_, Err(found)) | (Err(found), _) => ErrorKind::UnknownTag(found).into(),
+        }
+    }
+
+    pub(crate) fn incompatible_list_type(expected: u8, found: u8) -> Self {
+        match (Tag::try_from(expected), Tag::try_from(found)) {
+            (Ok(expected), Ok(found)) => ErrorKind::IncompatibleListType { expected, found }.into(),
+            (_, Err(found)) | (Err(found), _) => ErrorKind::UnknownTag(found).into(),
+        }
+    }
+
+    /// Records the stream offset, unless an inner value already did.
+    pub(crate) fn with_offset(mut self, offset: u64) -> Self {
+        self.inner.offset.get_or_insert(offset);
+        self
+    }
+
+    /// Prepends `segment` to the path, called while the error bubbles up towards the root.
+    pub(crate) fn at(mut self, segment: PathSegment) -> Self {
+        self.inner.path.insert(0, segment);
+        self
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Error {
+            inner: Box::new(ErrorImpl {
+                kind,
+                path: Vec::new(),
+                offset: None,
+            }),
+        }
+    }
 }
 
 impl From<std::io::Error> for Error {
     fn from(value: std::io::Error) -> Self {
-        Error::IoError(value)
+        ErrorKind::Io(value).into()
     }
 }
 
 impl From<TryFromIntError> for Error {
     fn from(value: TryFromIntError) -> Self {
-        Error::IntError(value)
+        ErrorKind::IntOutOfRange(value).into()
+    }
+}
+
+impl From<std::str::Utf8Error> for Error {
+    fn from(value: std::str::Utf8Error) -> Self {
+        ErrorKind::Utf8(value).into()
     }
 }
 
 impl From<std::string::FromUtf8Error> for Error {
     fn from(value: std::string::FromUtf8Error) -> Self {
-        Error::Uft8Error(value)
+        ErrorKind::Utf8(value.utf8_error()).into()
     }
 }
 
 impl ser::Error for Error {
-    fn custom<T: std::fmt::Display>(msg: T) -> Self {
-        Error::Message(msg.to_string())
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        ErrorKind::Message(msg.to_string()).into()
     }
 }
 
 impl de::Error for Error {
-    fn custom<T>(msg:T) -> Self where T:std::fmt::Display {
-        Error::Message(msg.to_string())
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        ErrorKind::Message(msg.to_string()).into()
     }
 }
 
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::Message(msg) => f.write_str(msg),
+            ErrorKind::Io(e) => write!(f, "i/o error: {}", e),
+            ErrorKind::Utf8(e) => write!(f, "invalid utf-8 in string: {}", e),
+            ErrorKind::IntOutOfRange(_) => f.write_str("integer out of range"),
+            ErrorKind::UnexpectedTag { expected, found } => {
+                write!(f, "expected {}, found {}", expected, found)
+            }
+            ErrorKind::UnknownTag(tag) => write!(f, "unknown tag id {}", tag),
+            ErrorKind::IncompatibleListType { expected, found } => {
+                write!(f, "list of {} can't hold a {}", expected, found)
+            }
+            ErrorKind::ListLengthMismatch { expected, found } => {
+                write!(f, "list of length {} got {} elements", expected, found)
+            }
+            ErrorKind::ExpectedEnd => f.write_str("expected TAG_End"),
+            ErrorKind::UnbalancedEnd {
+                open: Some(open),
+                found,
+            } => {
+                write!(f, "can't close a {} while a {} is open", found, open)
+            }
+            ErrorKind::UnbalancedEnd { open: None, found } => {
+                write!(f, "can't close a {} while nothing is open", found)
+            }
+            ErrorKind::DepthLimitExceeded { max } => {
+                write!(f, "nesting deeper than {} levels", max)
+            }
+            ErrorKind::SizeLimitExceeded { max } => write!(f, "input larger than {} bytes", max),
+            ErrorKind::LengthLimitExceeded { len, max } => {
+                write!(f, "length {} exceeds the limit of {}", len, max)
+            }
+            ErrorKind::KeyMustBeAString => f.write_str("compound keys must be strings"),
+            ErrorKind::NotFound => f.write_str("no value found"),
+            ErrorKind::NoMatch(path) => write!(f, "nothing matches `{}`", path),
+            ErrorKind::InvalidPath(segment) => write!(f, "invalid path segment `{}`", segment),
+            ErrorKind::UnsignedRejected => f.write_str("unsigned integers are rejected"),
+            ErrorKind::UnknownCompression(id) => write!(f, "unknown chunk compression {}", id),
+            ErrorKind::UnsupportedCompression(name) => {
+                write!(f, "unsupported custom chunk compression `{}`", name)
+            }
+            ErrorKind::UnknownRegionPath => {
+                f.write_str("chunk is stored in an external file but the region's path is unknown")
+            }
+            ErrorKind::InvalidChunkLength(len) => write!(f, "invalid chunk length {}", len),
+            ErrorKind::ChunkTooLarge(len) => {
+                write!(f, "chunk of {} bytes doesn't fit into a region file", len)
+            }
+            ErrorKind::UnsupportedChunkVersion(version) => {
+                write!(f, "unsupported chunk version {}", version)
+            }
+            ErrorKind::InvalidBlockState(state) => write!(f, "invalid block state `{}`", state),
+            ErrorKind::InvalidSchematic(reason) => write!(f, "invalid schematic: {}", reason),
+            ErrorKind::UnsupportedSchematicVersion(version) => {
+                write!(f, "unsupported schematic version {}", version)
+            }
+            ErrorKind::OutOfBounds(pos) => write!(f, "block position {:?} is out of bounds", pos),
+        }
     }
 }
 
-impl std::error::Error for Error {}
+impl fmt::Display for PathSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathSegment::Key(key) => f.write_str(key),
+            PathSegment::Index(index) => write!(f, "[{}]", index),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.inner.kind)?;
+        if !self.inner.path.is_empty() {
+            f.write_str(" at ")?;
+            for (i, segment) in self.inner.path.iter().enumerate() {
+                if i > 0 && matches!(segment, PathSegment::Key(_)) {
+                    f.write_str(".")?;
+                }
+                write!(f, "{}", segment)?;
+            }
+        }
+        if let Some(offset) = self.inner.offset {
+            write!(f, " (offset {})", offset)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Error")
+            .field("kind", &self.inner.kind)
+            .field("path", &self.inner.path)
+            .field("offset", &self.inner.offset)
+            .finish()
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.inner.kind {
+            ErrorKind::Io(e) => Some(e),
+            ErrorKind::Utf8(e) => Some(e),
+            ErrorKind::IntOutOfRange(e) => Some(e),
+            _ => None,
+        }
+    }
+}
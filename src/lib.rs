@@ -1,11 +1,41 @@
+#[macro_use]
+mod trace;
+
 mod nbt;
 
+mod array;
 mod de;
 mod error;
+mod lz4;
+#[cfg(feature = "models")]
+pub mod models;
+mod options;
+mod packed;
+mod path;
+mod read;
+mod reader;
+mod region;
 mod ser;
+mod value;
+mod world;
+mod writer;
 
+pub use array::{ByteArray, IntArray, LongArray, NibbleArray};
 pub use de::from_reader;
+pub use de::from_gzip_reader;
+pub use de::from_reader_at_path;
 pub use de::from_bytes;
+pub use de::from_slice;
 pub use de::Deserializer;
-pub use error::{Error, Result};
-pub use ser::{to_writer, Serializer};
+pub use error::{Error, ErrorKind, PathSegment, Result};
+pub use nbt::Tag;
+pub use options::{Coercion, Limits, UnsignedPolicy};
+pub use packed::{PackedArray, Packing};
+pub use path::NbtPath;
+pub use read::{IoRead, SliceRead};
+pub use reader::{Event, NbtReader};
+pub use region::{Chunks, Compression, RawChunk, RegionReader, RegionWriter};
+pub use ser::{to_gzip_writer, to_writer, Serializer};
+pub use value::{Compound, Value};
+pub use world::{Dimension, Storage, World, WorldChunks};
+pub use writer::NbtWriter;
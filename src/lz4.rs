@@ -0,0 +1,102 @@
+//! The block stream format of lz4-java's `LZ4BlockOutputStream`, which the game writes for chunks
+//! compressed with LZ4.
+//!
+//! A stream is a sequence of blocks, each behind a 21 byte header: the magic `LZ4Block`, a token
+//! holding the compression method and level, then the compressed length, the uncompressed length
+//! and a checksum of the uncompressed data, all little-endian. An empty block ends the stream.
+
+use std::io;
+
+use twox_hash::XxHash32;
+
+use crate::error::Result;
+
+const MAGIC: &[u8; 8] = b"LZ4Block";
+const HEADER_LEN: usize = 21;
+const METHOD_RAW: u8 = 0x10;
+const METHOD_LZ4: u8 = 0x20;
+/// lz4-java's default block size of 64 KiB, stored in the token as `log2(size) - 10`.
+const BLOCK_LEN: usize = 1 << 16;
+const LEVEL: u8 = 6;
+/// The largest block lz4-java writes.
+const MAX_BLOCK_LEN: usize = 1 << 25;
+const SEED: u32 = 0x9747b28c;
+
+pub(crate) fn decompress(mut data: &[u8]) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    loop {
+        if data.len() < HEADER_LEN || &data[..8] != MAGIC {
+            return Err(invalid("missing LZ4 block header"));
+        }
+        let token = data[8];
+        let compressed = u32::from_le_bytes(data[9..13].try_into().unwrap()) as usize;
+        let len = u32::from_le_bytes(data[13..17].try_into().unwrap()) as usize;
+        let checksum = u32::from_le_bytes(data[17..21].try_into().unwrap());
+        data = &data[HEADER_LEN..];
+
+        if len == 0 {
+            return Ok(output);
+        }
+        if len > MAX_BLOCK_LEN || compressed > data.len() {
+            return Err(invalid("invalid LZ4 block length"));
+        }
+        let (block, rest) = data.split_at(compressed);
+        data = rest;
+
+        let start = output.len();
+        match token & 0xf0 {
+            METHOD_RAW if compressed == len => output.extend_from_slice(block),
+            METHOD_LZ4 => {
+                output.resize(start + len, 0);
+                let written = lz4_flex::block::decompress_into(block, &mut output[start..])
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                if written != len {
+                    return Err(invalid("invalid LZ4 block length"));
+                }
+            }
+            _ => return Err(invalid("invalid LZ4 block method")),
+        }
+        if block_checksum(&output[start..]) != checksum {
+            return Err(invalid("LZ4 block checksum mismatch"));
+        }
+    }
+}
+
+pub(crate) fn compress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    for block in data.chunks(BLOCK_LEN) {
+        let compressed = lz4_flex::block::compress(block);
+        let (method, payload) = match compressed.len() < block.len() {
+            true => (METHOD_LZ4, compressed.as_slice()),
+            false => (METHOD_RAW, block),
+        };
+        write_header(
+            &mut output,
+            method,
+            payload.len(),
+            block.len(),
+            block_checksum(block),
+        );
+        output.extend_from_slice(payload);
+    }
+    write_header(&mut output, METHOD_RAW, 0, 0, 0);
+
+    output
+}
+
+fn write_header(output: &mut Vec<u8>, method: u8, compressed: usize, len: usize, checksum: u32) {
+    output.extend_from_slice(MAGIC);
+    output.push(method | LEVEL);
+    output.extend_from_slice(&(compressed as u32).to_le_bytes());
+    output.extend_from_slice(&(len as u32).to_le_bytes());
+    output.extend_from_slice(&checksum.to_le_bytes());
+}
+
+/// lz4-java's checksum, an xxHash32 cut to 28 bits.
+fn block_checksum(data: &[u8]) -> u32 {
+    XxHash32::oneshot(SEED, data) & 0x0fff_ffff
+}
+
+fn invalid(message: &str) -> crate::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message).into()
+}
@@ -0,0 +1,380 @@
+//! Chunks as stored in region files, in the format used since 1.18.
+//!
+//! Only the fields most tools need are modelled, the others are kept in the catch-alls.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    array::{LongArray, NibbleArray},
+    de::from_slice,
+    error::{Error, ErrorKind, Result},
+    packed::{self, PackedArray, Packing},
+    value::Compound,
+};
+
+/// The `DataVersion` of 21w43a, the first version writing this format.
+pub const MIN_DATA_VERSION: i32 = 2844;
+
+/// A chunk, read with [`Chunk::from_bytes`] or [`RegionReader::chunk`](crate::RegionReader::chunk).
+///
+/// The `DataVersion` is checked once the chunk is decoded, with the fields older chunks keep
+/// under `Level` treated as optional until then. So a chunk older than [`MIN_DATA_VERSION`]
+/// fails with an "unsupported chunk version" error on a best-effort basis: one whose top level
+/// entries have unexpected types fails on those instead. Only [`Chunk::from_bytes`] returns the
+/// typed [`ErrorKind::UnsupportedChunkVersion`], serde turns it into a message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "ChunkData")]
+pub struct Chunk {
+    #[serde(rename = "DataVersion")]
+    pub data_version: i32,
+    #[serde(rename = "xPos")]
+    pub x: i32,
+    /// The `Y` of the lowest section.
+    #[serde(rename = "yPos")]
+    pub y: i32,
+    #[serde(rename = "zPos")]
+    pub z: i32,
+    /// The generation status, e.g. `minecraft:full`.
+    #[serde(rename = "Status")]
+    pub status: String,
+    #[serde(rename = "LastUpdate", default)]
+    pub last_update: i64,
+    #[serde(rename = "InhabitedTime", default)]
+    pub inhabited_time: i64,
+    #[serde(default)]
+    pub sections: Vec<Section>,
+    #[serde(default)]
+    pub block_entities: Vec<Compound>,
+    /// Heights by heightmap name, e.g. `WORLD_SURFACE`, 9 bits each.
+    #[serde(rename = "Heightmaps", default)]
+    pub heightmaps: HashMap<String, LongArray>,
+    /// Entities, structures, ticks and everything else not modelled above.
+    #[serde(flatten)]
+    pub other: Compound,
+}
+
+impl Chunk {
+    /// Reads a chunk from its uncompressed NBT, e.g. from
+    /// [`RegionReader::chunk_bytes`](crate::RegionReader::chunk_bytes).
+    ///
+    /// Chunks older than [`MIN_DATA_VERSION`] fail with
+    /// [`ErrorKind::UnsupportedChunkVersion`], see [`Chunk`].
+    pub fn from_bytes(nbt: &[u8]) -> Result<Self> {
+        Chunk::try_from(from_slice::<ChunkData>(nbt)?)
+    }
+
+    /// Whether the chunk was written in the format modelled here. Always true for chunks that
+    /// were read, which fail otherwise.
+    pub fn is_supported(&self) -> bool {
+        self.data_version >= MIN_DATA_VERSION
+    }
+
+    /// The section at section height `y`.
+    pub fn section(&self, y: i32) -> Option<&Section> {
+        self.sections
+            .iter()
+            .find(|section| i32::from(section.y) == y)
+    }
+
+    /// The block at absolute height `y`. `x` and `z` may be world coordinates, only their
+    /// position within the chunk is used.
+    ///
+    /// Returns `None` if there is no section with block states at that height or its data is
+    /// malformed.
+    pub fn block_at(&self, x: i32, y: i32, z: i32) -> Option<&BlockState> {
+        let section = self.section(y.div_euclid(16))?;
+        section.block_at(
+            x.rem_euclid(16) as usize,
+            y.rem_euclid(16) as usize,
+            z.rem_euclid(16) as usize,
+        )
+    }
+
+    /// The biome at a block, like [`Chunk::block_at`].
+    pub fn biome_at(&self, x: i32, y: i32, z: i32) -> Option<&str> {
+        let biomes = self.section(y.div_euclid(16))?.biomes.as_ref()?;
+        biomes.get(
+            x.rem_euclid(16) as usize / 4,
+            y.rem_euclid(16) as usize / 4,
+            z.rem_euclid(16) as usize / 4,
+        )
+    }
+}
+
+/// A 16×16×16 part of a chunk.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Section {
+    #[serde(rename = "Y")]
+    pub y: i8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_states: Option<BlockStates>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub biomes: Option<Biomes>,
+    #[serde(
+        rename = "BlockLight",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub block_light: Option<NibbleArray>,
+    #[serde(rename = "SkyLight", default, skip_serializing_if = "Option::is_none")]
+    pub sky_light: Option<NibbleArray>,
+    #[serde(flatten)]
+    pub other: Compound,
+}
+
+impl Section {
+    /// The block at coordinates within the section, each between 0 and 15.
+    pub fn block_at(&self, x: usize, y: usize, z: usize) -> Option<&BlockState> {
+        self.block_states.as_ref()?.get(x, y, z)
+    }
+}
+
+/// A block and its properties, e.g. `minecraft:oak_stairs` with `facing=east`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BlockState {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(
+        rename = "Properties",
+        default,
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub properties: BTreeMap<String, String>,
+}
+
+impl BlockState {
+    /// A block state without properties.
+    pub fn new(name: impl Into<String>) -> Self {
+        BlockState {
+            name: name.into(),
+            properties: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_property(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.properties.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn property(&self, name: &str) -> Option<&str> {
+        self.properties.get(name).map(String::as_str)
+    }
+}
+
+/// Formats the state like commands do, e.g. `minecraft:oak_stairs[facing=east,half=top]`.
+impl fmt::Display for BlockState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)?;
+        for (i, (name, value)) in self.properties.iter().enumerate() {
+            let separator = if i == 0 { '[' } else { ',' };
+            write!(f, "{}{}={}", separator, name, value)?;
+        }
+        if !self.properties.is_empty() {
+            f.write_str("]")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses the format written by `Display`, failing with [`ErrorKind::InvalidBlockState`].
+impl FromStr for BlockState {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::from(ErrorKind::InvalidBlockState(s.to_string()));
+        let (name, properties) = match s.split_once('[') {
+            Some((name, rest)) => (name, Some(rest.strip_suffix(']').ok_or_else(invalid)?)),
+            None => (s, None),
+        };
+        if name.is_empty() || name.contains(']') {
+            return Err(invalid());
+        }
+
+        let mut state = BlockState::new(name);
+        for property in properties.into_iter().flat_map(|p| p.split(',')) {
+            let (name, value) = property.split_once('=').ok_or_else(invalid)?;
+            if name.is_empty() || value.is_empty() {
+                return Err(invalid());
+            }
+            state.properties.insert(name.to_string(), value.to_string());
+        }
+
+        Ok(state)
+    }
+}
+
+/// The blocks of a section: a palette and an index into it for each of the 4096 blocks, in YZX
+/// order.
+///
+/// `data` is left out when the palette has a single entry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockStates {
+    pub palette: Vec<BlockState>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<LongArray>,
+}
+
+impl BlockStates {
+    /// Bits per index: enough for the palette, but at least 4.
+    pub fn bits_per_entry(&self) -> u32 {
+        index_bits(self.palette.len()).max(4)
+    }
+
+    /// The block at coordinates within the section, `None` unless each is between 0 and 15.
+    pub fn get(&self, x: usize, y: usize, z: usize) -> Option<&BlockState> {
+        if x >= 16 || y >= 16 || z >= 16 {
+            return None;
+        }
+        lookup(
+            &self.palette,
+            self.data.as_ref(),
+            (y * 16 + z) * 16 + x,
+            self.bits_per_entry(),
+        )
+    }
+
+    /// The palette indices of all blocks.
+    pub fn indices(&self) -> Result<PackedArray> {
+        indices(
+            &self.palette,
+            self.data.as_ref(),
+            4096,
+            self.bits_per_entry(),
+        )
+    }
+}
+
+/// The biomes of a section: a palette and an index into it for each 4×4×4 cell, in YZX order.
+///
+/// `data` is left out when the palette has a single entry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Biomes {
+    pub palette: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<LongArray>,
+}
+
+impl Biomes {
+    /// Bits per index: just enough for the palette.
+    pub fn bits_per_entry(&self) -> u32 {
+        index_bits(self.palette.len())
+    }
+
+    /// The biome of a cell, `None` unless each cell coordinate is between 0 and 3.
+    pub fn get(&self, x: usize, y: usize, z: usize) -> Option<&str> {
+        if x >= 4 || y >= 4 || z >= 4 {
+            return None;
+        }
+        lookup(
+            &self.palette,
+            self.data.as_ref(),
+            (y * 4 + z) * 4 + x,
+            self.bits_per_entry(),
+        )
+        .map(String::as_str)
+    }
+
+    /// The palette indices of all cells.
+    pub fn indices(&self) -> Result<PackedArray> {
+        indices(&self.palette, self.data.as_ref(), 64, self.bits_per_entry())
+    }
+}
+
+/// The number of bits needed to index a palette of `len` entries.
+pub(crate) fn index_bits(len: usize) -> u32 {
+    usize::BITS - len.saturating_sub(1).leading_zeros()
+}
+
+fn lookup<'a, T>(
+    palette: &'a [T],
+    data: Option<&LongArray>,
+    index: usize,
+    bits: u32,
+) -> Option<&'a T> {
+    match (palette, data) {
+        // a single entry needs no data, and the game ignores any that is there
+        ([single], _) => Some(single),
+        (_, Some(data)) => {
+            let entry = packed::read(data, index, bits, Packing::Aligned)?;
+            palette.get(entry as usize)
+        }
+        // several entries without data are malformed
+        (_, None) => None,
+    }
+}
+
+/// Fails with [`ErrorKind::ListLengthMismatch`] if a palette of several entries comes with
+/// missing or short data.
+fn indices<T>(
+    palette: &[T],
+    data: Option<&LongArray>,
+    len: usize,
+    bits: u32,
+) -> Result<PackedArray> {
+    match palette.len() {
+        0 | 1 => Ok(PackedArray::new(len, bits.max(1), Packing::Aligned)),
+        _ => {
+            let data = data.cloned().unwrap_or_default();
+            PackedArray::from_long_array(data, len, bits, Packing::Aligned)
+        }
+    }
+}
+
+/// A [`Chunk`] as read, with the fields older chunks keep under `Level` optional, so the
+/// version can be checked before they are required. Chunks from before 1.9 have no
+/// `DataVersion`.
+#[derive(Deserialize)]
+struct ChunkData {
+    #[serde(rename = "DataVersion", default)]
+    data_version: i32,
+    #[serde(rename = "xPos")]
+    x: Option<i32>,
+    #[serde(rename = "yPos")]
+    y: Option<i32>,
+    #[serde(rename = "zPos")]
+    z: Option<i32>,
+    #[serde(rename = "Status")]
+    status: Option<String>,
+    #[serde(rename = "LastUpdate", default)]
+    last_update: i64,
+    #[serde(rename = "InhabitedTime", default)]
+    inhabited_time: i64,
+    #[serde(default)]
+    sections: Vec<Section>,
+    #[serde(default)]
+    block_entities: Vec<Compound>,
+    #[serde(rename = "Heightmaps", default)]
+    heightmaps: HashMap<String, LongArray>,
+    #[serde(flatten)]
+    other: Compound,
+}
+
+impl TryFrom<ChunkData> for Chunk {
+    type Error = Error;
+
+    fn try_from(data: ChunkData) -> Result<Self> {
+        if data.data_version < MIN_DATA_VERSION {
+            return Err(ErrorKind::UnsupportedChunkVersion(data.data_version).into());
+        }
+        let missing = <Error as serde::de::Error>::missing_field;
+
+        Ok(Chunk {
+            data_version: data.data_version,
+            x: data.x.ok_or_else(|| missing("xPos"))?,
+            y: data.y.ok_or_else(|| missing("yPos"))?,
+            z: data.z.ok_or_else(|| missing("zPos"))?,
+            status: data.status.ok_or_else(|| missing("Status"))?,
+            last_update: data.last_update,
+            inhabited_time: data.inhabited_time,
+            sections: data.sections,
+            block_entities: data.block_entities,
+            heightmaps: data.heightmaps,
+            other: data.other,
+        })
+    }
+}
@@ -0,0 +1,181 @@
+//! The `Data` compound of `level.dat`.
+//!
+//! Field names follow 1.21, entries written by older or newer versions end up in the catch-alls.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::player::Player;
+use crate::{de::from_gzip_reader, error::Result, ser::to_gzip_writer, value::Compound};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Level {
+    #[serde(
+        rename = "DataVersion",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub data_version: Option<i32>,
+    #[serde(rename = "LevelName", default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The game version that last saved the world.
+    #[serde(rename = "Version", default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<Version>,
+    /// The storage format, 19133 for anvil.
+    #[serde(rename = "version", default, skip_serializing_if = "Option::is_none")]
+    pub storage_version: Option<i32>,
+    #[serde(rename = "GameType", default, skip_serializing_if = "Option::is_none")]
+    pub game_type: Option<i32>,
+    #[serde(
+        rename = "Difficulty",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub difficulty: Option<i8>,
+    #[serde(
+        with = "super::byte_bool::option",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub hardcore: Option<bool>,
+    #[serde(
+        rename = "allowCommands",
+        with = "super::byte_bool::option",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub allow_commands: Option<bool>,
+    #[serde(rename = "SpawnX", default, skip_serializing_if = "Option::is_none")]
+    pub spawn_x: Option<i32>,
+    #[serde(rename = "SpawnY", default, skip_serializing_if = "Option::is_none")]
+    pub spawn_y: Option<i32>,
+    #[serde(rename = "SpawnZ", default, skip_serializing_if = "Option::is_none")]
+    pub spawn_z: Option<i32>,
+    #[serde(
+        rename = "SpawnAngle",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub spawn_angle: Option<f32>,
+    /// Ticks since the world was created.
+    #[serde(rename = "Time", default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<i64>,
+    /// Ticks since the world was created, adjusted by sleeping and `/time`.
+    #[serde(rename = "DayTime", default, skip_serializing_if = "Option::is_none")]
+    pub day_time: Option<i64>,
+    /// Unix time in milliseconds.
+    #[serde(
+        rename = "LastPlayed",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub last_played: Option<i64>,
+    #[serde(
+        with = "super::byte_bool::option",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub raining: Option<bool>,
+    #[serde(rename = "rainTime", default, skip_serializing_if = "Option::is_none")]
+    pub rain_time: Option<i32>,
+    #[serde(
+        with = "super::byte_bool::option",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub thundering: Option<bool>,
+    #[serde(
+        rename = "thunderTime",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub thunder_time: Option<i32>,
+    /// Game rules by name, with their values as strings, e.g. `"true"` or `"3"`.
+    #[serde(rename = "GameRules", default, skip_serializing_if = "Option::is_none")]
+    pub game_rules: Option<BTreeMap<String, String>>,
+    #[serde(
+        rename = "WorldGenSettings",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub world_gen_settings: Option<WorldGenSettings>,
+    /// The player of a singleplayer world.
+    #[serde(rename = "Player", default, skip_serializing_if = "Option::is_none")]
+    pub player: Option<Player>,
+    #[serde(flatten)]
+    pub other: Compound,
+}
+
+/// The root of `level.dat`, which only holds `Data`.
+#[derive(Serialize, Deserialize)]
+struct LevelDat<L> {
+    #[serde(rename = "Data")]
+    data: L,
+}
+
+impl Level {
+    /// Reads `level.dat`, gzipped or not.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let file: LevelDat<Level> = from_gzip_reader(BufReader::new(File::open(path)?))?;
+        Ok(file.data)
+    }
+
+    /// Writes a gzipped `level.dat`, like the game does.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        to_gzip_writer(writer, &LevelDat { data: self }, "")
+    }
+
+    pub fn game_rule(&self, name: &str) -> Option<&str> {
+        self.game_rules.as_ref()?.get(name).map(String::as_str)
+    }
+
+    /// The world spawn, with missing coordinates as 0.
+    pub fn spawn(&self) -> [i32; 3] {
+        [self.spawn_x, self.spawn_y, self.spawn_z].map(|coordinate| coordinate.unwrap_or(0))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Version {
+    /// The data version of the game.
+    #[serde(rename = "Id")]
+    pub id: i32,
+    /// E.g. `1.21.4`.
+    #[serde(rename = "Name")]
+    pub name: String,
+    /// `main`, or the name of an experimental branch.
+    #[serde(rename = "Series", default, skip_serializing_if = "Option::is_none")]
+    pub series: Option<String>,
+    #[serde(
+        rename = "Snapshot",
+        with = "super::byte_bool::option",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub snapshot: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorldGenSettings {
+    pub seed: i64,
+    #[serde(
+        with = "super::byte_bool::option",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub generate_features: Option<bool>,
+    #[serde(
+        with = "super::byte_bool::option",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub bonus_chest: Option<bool>,
+    /// The generator of each dimension, by dimension id.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<Compound>,
+}
@@ -0,0 +1,71 @@
+//! Typed structs for files the game writes, built on the serde support of this crate.
+//!
+//! Structs with a catch-all `other` field keep the entries they don't model there, so a file
+//! that is read and written again loses nothing.
+
+pub mod chunk;
+pub mod level;
+pub mod player;
+pub mod schematic;
+pub mod structure;
+
+/// `#[serde(with)]` for bools in structs with a flattened catch-all.
+///
+/// Such structs are buffered by serde before they are deserialized, which only turns actual
+/// bools into bools, but NBT stores them as bytes.
+pub(crate) mod byte_bool {
+    use std::fmt;
+
+    use serde::{de, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &bool, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bool(*value)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+        struct BoolVisitor;
+
+        impl de::Visitor<'_> for BoolVisitor {
+            type Value = bool;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a bool or a byte")
+            }
+
+            fn visit_bool<E: de::Error>(self, v: bool) -> Result<bool, E> {
+                Ok(v)
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<bool, E> {
+                Ok(v != 0)
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<bool, E> {
+                Ok(v != 0)
+            }
+        }
+
+        deserializer.deserialize_any(BoolVisitor)
+    }
+
+    /// The same for optional bools, which have to be skipped when they are `None`.
+    pub mod option {
+        use serde::{Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(
+            value: &Option<bool>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match value {
+                Some(value) => super::serialize(value, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<bool>, D::Error> {
+            super::deserialize(deserializer).map(Some)
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! Players, as stored in `playerdata/<uuid>.dat` and under `Data.Player` in `level.dat`.
+//!
+//! Field names follow 1.21, entries written by older versions end up in the catch-alls.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    array::IntArray, de::from_gzip_reader, error::Result, ser::to_gzip_writer, value::Compound,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Player {
+    #[serde(
+        rename = "DataVersion",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub data_version: Option<i32>,
+    #[serde(rename = "UUID", default, skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<IntArray>,
+    /// The dimension the player is in, e.g. `minecraft:overworld`.
+    #[serde(rename = "Dimension", default, skip_serializing_if = "Option::is_none")]
+    pub dimension: Option<String>,
+    #[serde(rename = "Pos", default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<[f64; 3]>,
+    #[serde(rename = "Motion", default, skip_serializing_if = "Option::is_none")]
+    pub motion: Option<[f64; 3]>,
+    /// Yaw and pitch in degrees.
+    #[serde(rename = "Rotation", default, skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<[f32; 2]>,
+    #[serde(rename = "Health", default, skip_serializing_if = "Option::is_none")]
+    pub health: Option<f32>,
+    #[serde(rename = "foodLevel", default, skip_serializing_if = "Option::is_none")]
+    pub food_level: Option<i32>,
+    #[serde(rename = "XpLevel", default, skip_serializing_if = "Option::is_none")]
+    pub xp_level: Option<i32>,
+    #[serde(rename = "XpTotal", default, skip_serializing_if = "Option::is_none")]
+    pub xp_total: Option<i32>,
+    #[serde(
+        rename = "playerGameType",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub game_type: Option<i32>,
+    #[serde(
+        rename = "SelectedItemSlot",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub selected_slot: Option<i32>,
+    #[serde(rename = "Inventory", default, skip_serializing_if = "Option::is_none")]
+    pub inventory: Option<Vec<Item>>,
+    #[serde(
+        rename = "EnderItems",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub ender_items: Option<Vec<Item>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abilities: Option<Abilities>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attributes: Vec<Attribute>,
+    #[serde(flatten)]
+    pub other: Compound,
+}
+
+impl Player {
+    /// Reads a player file, gzipped or not.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        from_gzip_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Writes a gzipped player file, like the game does.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        to_gzip_writer(BufWriter::new(File::create(path)?), self, "")
+    }
+
+    /// The stack in an inventory slot.
+    pub fn item(&self, slot: i8) -> Option<&Item> {
+        self.inventory
+            .iter()
+            .flatten()
+            .find(|item| item.slot == Some(slot))
+    }
+}
+
+/// A stack of items in an inventory slot.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Item {
+    #[serde(rename = "Slot", default, skip_serializing_if = "Option::is_none")]
+    pub slot: Option<i8>,
+    pub id: String,
+    /// The stack size, a missing count means a single item.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<i32>,
+    /// Data components like a custom name or enchantments.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub components: Option<Compound>,
+    #[serde(flatten)]
+    pub other: Compound,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Abilities {
+    #[serde(
+        with = "super::byte_bool::option",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub flying: Option<bool>,
+    #[serde(
+        with = "super::byte_bool::option",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub mayfly: Option<bool>,
+    /// Whether the player is in creative mode.
+    #[serde(
+        with = "super::byte_bool::option",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub instabuild: Option<bool>,
+    #[serde(
+        with = "super::byte_bool::option",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub invulnerable: Option<bool>,
+    #[serde(
+        with = "super::byte_bool::option",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub may_build: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fly_speed: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub walk_speed: Option<f32>,
+    #[serde(flatten)]
+    pub other: Compound,
+}
+
+/// An attribute like `minecraft:max_health`, whose modifiers are kept in `other`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attribute {
+    pub id: String,
+    pub base: f64,
+    #[serde(flatten)]
+    pub other: Compound,
+}
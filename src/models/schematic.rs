@@ -0,0 +1,725 @@
+//! Schematics exchanged between building tools: Sponge `.schem` files of versions 1 to 3 and
+//! Litematica `.litematic` files.
+//!
+//! Both are read into and written from a [`Schematic`], which is how one is converted into the
+//! other. Entities and biomes aren't kept.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{de::IgnoredAny, Deserialize, Serialize};
+
+use super::chunk::{index_bits, BlockState};
+use crate::{
+    array::{ByteArray, IntArray, LongArray},
+    de::{from_gzip_reader, from_slice, gunzip},
+    error::{Error, ErrorKind, Result},
+    packed::{PackedArray, Packing},
+    ser::to_gzip_writer,
+    value::{remove_entry, Compound, Value},
+};
+
+const AIR: &str = "minecraft:air";
+/// The Litematica format version written, read by Litematica for 1.20 and later.
+const LITEMATIC_VERSION: i32 = 6;
+
+/// The Sponge schematic versions written by [`Schematic::write_sponge`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SpongeVersion {
+    /// Version 2, with the blocks at the root.
+    V2,
+    /// Version 3, with everything in a `Schematic` compound and the blocks in `Blocks`.
+    V3,
+}
+
+/// A box of blocks, each an index into a palette.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schematic {
+    pub data_version: i32,
+    /// Width, height and length, along x, y and z.
+    pub size: [i32; 3],
+    /// Where the box starts relative to the position it was copied from or is pasted at.
+    pub offset: [i32; 3],
+    pub palette: Vec<BlockState>,
+    /// An index into the palette for each block, in YZX order.
+    pub blocks: Vec<u32>,
+    pub block_entities: Vec<BlockEntity>,
+}
+
+/// A block entity, with its position in the schematic. Its `nbt` includes its `id`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockEntity {
+    pub pos: [i32; 3],
+    pub nbt: Compound,
+}
+
+impl Schematic {
+    /// A schematic full of air. Panics if `size` is negative or its volume doesn't fit into a
+    /// `usize`.
+    pub fn new(data_version: i32, size: [i32; 3]) -> Self {
+        Schematic::air(data_version, size).expect("negative or too large schematic size")
+    }
+
+    /// Reads a `.litematic` file, or a Sponge schematic for any other extension.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = BufReader::new(File::open(path)?);
+        match is_litematic(path) {
+            true => Schematic::read_litematic(file),
+            false => Schematic::read_sponge(file),
+        }
+    }
+
+    /// Writes a `.litematic` file named after the file, or a version 3 Sponge schematic for any
+    /// other extension.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let file = BufWriter::new(File::create(path)?);
+        match is_litematic(path) {
+            true => {
+                let name = path.file_stem().unwrap_or_default().to_string_lossy();
+                self.write_litematic(file, &name)
+            }
+            false => self.write_sponge(file, SpongeVersion::V3),
+        }
+    }
+
+    /// Places `state` at `pos`, see [`Schematic::set_block`].
+    pub fn with_block(mut self, pos: [i32; 3], state: BlockState) -> Self {
+        self.set_block(pos, state);
+        self
+    }
+
+    /// Places `state` at `pos` with a block entity whose `nbt` includes its `id`.
+    pub fn with_block_entity(mut self, pos: [i32; 3], state: BlockState, nbt: Compound) -> Self {
+        self.set_block(pos, state);
+        self.block_entities.push(BlockEntity { pos, nbt });
+        self
+    }
+
+    /// The block at `pos`, relative to the schematic's corner.
+    pub fn block_at(&self, pos: [i32; 3]) -> Option<&BlockState> {
+        let index = *self.blocks.get(self.index(pos)?)?;
+        self.palette.get(index as usize)
+    }
+
+    /// Places `state` at `pos`, adding it to the palette if it is missing, and removes the block
+    /// entity that was there.
+    ///
+    /// Panics if `pos` is outside the schematic.
+    pub fn set_block(&mut self, pos: [i32; 3], state: BlockState) {
+        let index = self
+            .index(pos)
+            .unwrap_or_else(|| panic!("{:?} is outside the schematic", pos));
+        let state = intern(&mut self.palette, state);
+        self.blocks[index] = state;
+        self.block_entities.retain(|entity| entity.pos != pos);
+    }
+
+    pub fn block_entity(&self, pos: [i32; 3]) -> Option<&BlockEntity> {
+        self.block_entities.iter().find(|entity| entity.pos == pos)
+    }
+
+    /// Reads a Sponge schematic of version 1, 2 or 3, gzipped or not.
+    pub fn read_sponge<R: Read>(reader: R) -> Result<Self> {
+        // the version is nested in version 3, so it has to be known before reading the rest
+        let mut bytes = Vec::new();
+        gunzip(reader)?.read_to_end(&mut bytes)?;
+        let probe: SpongeProbe = from_slice(&bytes)?;
+
+        match (probe.schematic, probe.version) {
+            (Some(_), _) => {
+                let SpongeV3Root { schematic } = from_slice(&bytes)?;
+                if schematic.version != 3 {
+                    return Err(ErrorKind::UnsupportedSchematicVersion(schematic.version).into());
+                }
+                let size = [schematic.width, schematic.height, schematic.length];
+                let blocks = match schematic.blocks {
+                    Some(blocks) => blocks,
+                    // a schematic of only entities or biomes may leave out the blocks
+                    None => {
+                        let mut air = Schematic::air(schematic.data_version, sponge_size(size))?;
+                        air.offset = sponge_offset(&schematic.offset);
+                        return Ok(air);
+                    }
+                };
+                Schematic::from_sponge(
+                    schematic.data_version,
+                    size,
+                    &schematic.offset,
+                    blocks.palette,
+                    &blocks.data,
+                    blocks.block_entities,
+                    true,
+                )
+            }
+            (None, Some(1 | 2)) => {
+                let schematic: SpongeV2 = from_slice(&bytes)?;
+                Schematic::from_sponge(
+                    schematic.data_version,
+                    [schematic.width, schematic.height, schematic.length],
+                    &schematic.offset,
+                    schematic.palette,
+                    &schematic.block_data,
+                    schematic.block_entities,
+                    false,
+                )
+            }
+            (None, version) => {
+                Err(ErrorKind::UnsupportedSchematicVersion(version.unwrap_or(0)).into())
+            }
+        }
+    }
+
+    /// Writes a gzipped Sponge schematic. Sides longer than 65535 blocks fail with
+    /// [`ErrorKind::IntOutOfRange`].
+    pub fn write_sponge<W: Write>(&self, writer: W, version: SpongeVersion) -> Result<()> {
+        self.check()?;
+        let [width, height, length] = self.size.map(|side| u16::try_from(side).map(|s| s as i16));
+        let (width, height, length) = (width?, height?, length?);
+
+        // the ids are the states' strings, so duplicate entries share one
+        let mut palette = BTreeMap::new();
+        let mut ids = Vec::with_capacity(self.palette.len());
+        for state in &self.palette {
+            let len = i32::try_from(palette.len()).map_err(|_| too_large())?;
+            ids.push(*palette.entry(state.to_string()).or_insert(len));
+        }
+        let mut data = Vec::with_capacity(self.blocks.len());
+        for &block in &self.blocks {
+            write_varint(&mut data, ids[block as usize] as u32);
+        }
+
+        let nested = version == SpongeVersion::V3;
+        let block_entities = self
+            .block_entities
+            .iter()
+            .map(|entity| {
+                let mut nbt = entity.nbt.clone();
+                let id = match remove_entry(&mut nbt, "id") {
+                    Some(Value::String(id)) => id,
+                    _ => String::new(),
+                };
+                let other = match nested {
+                    true => [("Data".to_string(), Value::Compound(nbt))]
+                        .into_iter()
+                        .collect(),
+                    false => nbt,
+                };
+                SpongeBlockEntity {
+                    pos: IntArray(entity.pos.to_vec()),
+                    id,
+                    other,
+                }
+            })
+            .collect();
+        let offset = IntArray(self.offset.to_vec());
+
+        match version {
+            SpongeVersion::V2 => {
+                let schematic = SpongeV2 {
+                    version: 2,
+                    data_version: self.data_version,
+                    width,
+                    height,
+                    length,
+                    offset,
+                    palette_max: i32::try_from(palette.len()).map_err(|_| too_large())?,
+                    palette,
+                    block_data: ByteArray(data),
+                    block_entities,
+                };
+                to_gzip_writer(writer, &schematic, "Schematic")
+            }
+            SpongeVersion::V3 => {
+                let schematic = SpongeV3 {
+                    version: 3,
+                    data_version: self.data_version,
+                    width,
+                    height,
+                    length,
+                    offset,
+                    blocks: Some(SpongeBlocks {
+                        palette,
+                        data: ByteArray(data),
+                        block_entities,
+                    }),
+                };
+                to_gzip_writer(writer, &SpongeV3Root { schematic }, "")
+            }
+        }
+    }
+
+    /// Reads a Litematica file, gzipped or not. Its regions are merged into one box that
+    /// encloses all of them, with air in regions not overwriting other regions.
+    ///
+    /// The box may hold at most `i32::MAX` blocks, the most the file's metadata can count.
+    pub fn read_litematic<R: Read>(reader: R) -> Result<Self> {
+        let file: Litematic = from_gzip_reader(reader)?;
+
+        // the sizes come from the file, so nothing is allocated before they are checked against
+        // the block states actually stored
+        let mut regions = Vec::with_capacity(file.regions.len());
+        for region in file.regions.into_values() {
+            let (position, size) = (region.position.to_array(), region.size.to_array());
+            let (mut start, mut end, mut sides) = ([0; 3], [0; 3], [0; 3]);
+            for axis in 0..3 {
+                sides[axis] = size[axis].checked_abs().ok_or_else(too_large)?;
+                // regions with a negative size extend from their position towards negative
+                // coordinates
+                start[axis] = match size[axis] < 0 {
+                    true => position[axis].checked_add(size[axis] + 1),
+                    false => Some(position[axis]),
+                }
+                .ok_or_else(too_large)?;
+                end[axis] = start[axis].checked_add(sides[axis]).ok_or_else(too_large)?;
+            }
+
+            let len = volume(sides).ok_or_else(too_large)?;
+            let bits = index_bits(region.block_state_palette.len()).max(2);
+            let longs = len.checked_mul(bits as usize).map(|bits| bits.div_ceil(64));
+            if longs != Some(region.block_states.len()) {
+                return Err(invalid("block states don't match the region size"));
+            }
+            regions.push((start, end, len, bits, region));
+        }
+
+        let origin = [0, 1, 2].map(|axis| {
+            let starts = regions.iter().map(|(start, ..)| start[axis]);
+            starts.min().unwrap_or(0)
+        });
+        let mut size = [0; 3];
+        for axis in 0..3 {
+            let end = regions.iter().map(|(_, end, ..)| end[axis]).max();
+            size[axis] = end
+                .unwrap_or(0)
+                .checked_sub(origin[axis])
+                .ok_or_else(too_large)?;
+        }
+        if volume(size).is_none_or(|volume| volume > i32::MAX as usize) {
+            return Err(too_large());
+        }
+
+        let mut schematic = Schematic::new(file.minecraft_data_version, size);
+        schematic.offset = origin;
+        for (start, end, len, bits, region) in regions {
+            let corner = [0, 1, 2].map(|axis| start[axis] - origin[axis]);
+            let size = [0, 1, 2].map(|axis| end[axis] - start[axis]);
+            let states =
+                PackedArray::from_long_array(region.block_states, len, bits, Packing::Spanning)?;
+            let states_in_schematic: Vec<_> = region
+                .block_state_palette
+                .into_iter()
+                .map(|state| (state.name != AIR).then(|| intern(&mut schematic.palette, state)))
+                .collect();
+
+            let (width, length) = (size[0] as usize, size[2] as usize);
+            for (i, state) in states.iter().enumerate() {
+                let state = *states_in_schematic
+                    .get(state as usize)
+                    .ok_or_else(|| invalid("block state outside the palette"))?;
+                if let Some(state) = state {
+                    let pos = [i % width, i / (width * length), i / width % length];
+                    let pos = [0, 1, 2].map(|axis| corner[axis] + pos[axis] as i32);
+                    let index = schematic.index(pos).unwrap();
+                    schematic.blocks[index] = state;
+                }
+            }
+
+            for mut nbt in region.tile_entities {
+                let mut pos = [0; 3];
+                for (axis, key) in ["x", "y", "z"].into_iter().enumerate() {
+                    pos[axis] = match remove_entry(&mut nbt, key) {
+                        Some(Value::Int(v)) => corner[axis]
+                            .checked_add(v)
+                            .ok_or_else(|| invalid("block entity position out of range"))?,
+                        _ => return Err(invalid("block entity without position")),
+                    };
+                }
+                schematic.block_entities.push(BlockEntity { pos, nbt });
+            }
+        }
+
+        Ok(schematic)
+    }
+
+    /// Writes a gzipped Litematica file with a single region called `name`.
+    pub fn write_litematic<W: Write>(&self, writer: W, name: &str) -> Result<()> {
+        self.check()?;
+        let total_volume = i32::try_from(self.blocks.len()).map_err(|_| too_large())?;
+        // Litematica expects air first
+        let mut palette = vec![BlockState::new(AIR)];
+        let states: Vec<_> = self
+            .palette
+            .iter()
+            .map(|state| intern(&mut palette, state.clone()))
+            .collect();
+
+        let bits = index_bits(palette.len()).max(2);
+        let mut block_states = PackedArray::new(self.blocks.len(), bits, Packing::Spanning);
+        let mut total_blocks = 0;
+        for (i, &block) in self.blocks.iter().enumerate() {
+            let state = states[block as usize];
+            if state != 0 {
+                block_states.set(i, state);
+                total_blocks += 1;
+            }
+        }
+
+        let tile_entities = self
+            .block_entities
+            .iter()
+            .map(|entity| {
+                let mut nbt = entity.nbt.clone();
+                for (key, v) in ["x", "y", "z"].into_iter().zip(entity.pos) {
+                    nbt.insert(key.to_string(), Value::Int(v));
+                }
+                nbt
+            })
+            .collect();
+        let size = Vec3::from_array(self.size);
+        let region = LitematicRegion {
+            position: Vec3::default(),
+            size,
+            block_state_palette: palette,
+            block_states: block_states.into(),
+            tile_entities,
+            entities: Vec::new(),
+            pending_block_ticks: Vec::new(),
+            pending_fluid_ticks: Vec::new(),
+        };
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_millis() as i64);
+        let file = Litematic {
+            minecraft_data_version: self.data_version,
+            version: LITEMATIC_VERSION,
+            sub_version: Some(1),
+            metadata: LitematicMetadata {
+                name: name.to_string(),
+                author: String::new(),
+                description: String::new(),
+                region_count: 1,
+                total_blocks,
+                total_volume,
+                time_created: now,
+                time_modified: now,
+                enclosing_size: size,
+            },
+            regions: [(name.to_string(), region)].into_iter().collect(),
+        };
+        to_gzip_writer(writer, &file, "")
+    }
+
+    /// A schematic full of air, failing instead of aborting if the blocks can't be allocated.
+    fn air(data_version: i32, size: [i32; 3]) -> Result<Self> {
+        let volume = volume(size).ok_or_else(too_large)?;
+        let mut blocks = Vec::new();
+        blocks.try_reserve_exact(volume).map_err(|_| too_large())?;
+        blocks.resize(volume, 0);
+
+        Ok(Schematic {
+            data_version,
+            size,
+            offset: [0; 3],
+            palette: vec![BlockState::new(AIR)],
+            blocks,
+            block_entities: Vec::new(),
+        })
+    }
+
+    fn index(&self, pos: [i32; 3]) -> Option<usize> {
+        if (0..3).any(|axis| !(0..self.size[axis]).contains(&pos[axis])) {
+            return None;
+        }
+        let [x, y, z] = pos.map(|c| c as usize);
+        let [width, _, length] = self.size.map(|side| side as usize);
+        Some((y * length + z) * width + x)
+    }
+
+    /// Fails if the public fields were left inconsistent.
+    fn check(&self) -> Result<()> {
+        if Some(self.blocks.len()) != volume(self.size) {
+            return Err(invalid("block count doesn't match the size"));
+        }
+        if self
+            .blocks
+            .iter()
+            .any(|&b| b as usize >= self.palette.len())
+        {
+            return Err(invalid("block state outside the palette"));
+        }
+        Ok(())
+    }
+
+    fn from_sponge(
+        data_version: i32,
+        size: [i16; 3],
+        offset: &[i32],
+        palette: BTreeMap<String, i32>,
+        data: &[u8],
+        block_entities: Vec<SpongeBlockEntity>,
+        nested: bool,
+    ) -> Result<Self> {
+        // the sides are only trusted once the block data matches them
+        let size = sponge_size(size);
+        let blocks = read_varints(data)?;
+        if volume(size) != Some(blocks.len()) {
+            return Err(invalid("block count doesn't match the size"));
+        }
+
+        // ids missing from the palette are left as air
+        let mut states = vec![BlockState::new(AIR); palette.len()];
+        for (state, id) in palette {
+            let slot = usize::try_from(id)
+                .ok()
+                .and_then(|id| states.get_mut(id))
+                .ok_or_else(|| invalid("palette id out of range"))?;
+            *slot = state.parse()?;
+        }
+
+        let mut schematic = Schematic {
+            data_version,
+            size,
+            offset: sponge_offset(offset),
+            palette: states,
+            blocks,
+            block_entities: Vec::new(),
+        };
+        schematic.check()?;
+
+        for entity in block_entities {
+            let pos = match entity.pos[..] {
+                [x, y, z] => [x, y, z],
+                _ => return Err(invalid("block entity position isn't three ints")),
+            };
+            let mut nbt = entity.other;
+            if nested {
+                nbt = match remove_entry(&mut nbt, "Data") {
+                    Some(Value::Compound(data)) => data,
+                    _ => Compound::new(),
+                };
+            }
+            nbt.insert("id".to_string(), Value::String(entity.id));
+            schematic.block_entities.push(BlockEntity { pos, nbt });
+        }
+
+        Ok(schematic)
+    }
+}
+
+fn is_litematic(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "litematic")
+}
+
+/// The number of blocks, `None` if a side is negative or there are more than fit into a `usize`.
+fn volume(size: [i32; 3]) -> Option<usize> {
+    size.iter().try_fold(1usize, |volume, &side| {
+        volume.checked_mul(usize::try_from(side).ok()?)
+    })
+}
+
+/// The sides of a Sponge schematic, which are unsigned shorts.
+fn sponge_size(size: [i16; 3]) -> [i32; 3] {
+    size.map(|side| i32::from(side as u16))
+}
+
+fn sponge_offset(offset: &[i32]) -> [i32; 3] {
+    match *offset {
+        [x, y, z] => [x, y, z],
+        _ => [0; 3],
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    ErrorKind::InvalidSchematic(reason.to_string()).into()
+}
+
+fn too_large() -> Error {
+    invalid("region too large")
+}
+
+/// The index of `state` in `palette`, adding it if it is missing.
+fn intern(palette: &mut Vec<BlockState>, state: BlockState) -> u32 {
+    match palette.iter().position(|s| *s == state) {
+        Some(index) => index as u32,
+        None => {
+            palette.push(state);
+            palette.len() as u32 - 1
+        }
+    }
+}
+
+/// Sponge block data: one unsigned LEB128 varint per block.
+fn read_varints(data: &[u8]) -> Result<Vec<u32>> {
+    let mut values = Vec::with_capacity(data.len());
+    let (mut value, mut shift) = (0u32, 0);
+    for &byte in data {
+        if shift > 28 {
+            return Err(invalid("block data varint longer than five bytes"));
+        }
+        value |= u32::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            values.push(value);
+            (value, shift) = (0, 0);
+        } else {
+            shift += 7;
+        }
+    }
+    if shift != 0 {
+        return Err(invalid("block data ends inside a varint"));
+    }
+
+    Ok(values)
+}
+
+fn write_varint(data: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        data.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    data.push(value as u8);
+}
+
+/// Just enough of a Sponge schematic to tell the versions apart.
+#[derive(Deserialize)]
+struct SpongeProbe {
+    #[serde(rename = "Schematic", default)]
+    schematic: Option<IgnoredAny>,
+    #[serde(rename = "Version", default)]
+    version: Option<i32>,
+}
+
+/// Versions 1 and 2, which only differ in names.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SpongeV2 {
+    version: i32,
+    /// Missing in version 1.
+    #[serde(default)]
+    data_version: i32,
+    width: i16,
+    height: i16,
+    length: i16,
+    #[serde(default)]
+    offset: IntArray,
+    #[serde(default)]
+    palette_max: i32,
+    palette: BTreeMap<String, i32>,
+    block_data: ByteArray,
+    #[serde(default, alias = "TileEntities")]
+    block_entities: Vec<SpongeBlockEntity>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SpongeV3Root {
+    #[serde(rename = "Schematic")]
+    schematic: SpongeV3,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SpongeV3 {
+    version: i32,
+    data_version: i32,
+    width: i16,
+    height: i16,
+    length: i16,
+    #[serde(default)]
+    offset: IntArray,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    blocks: Option<SpongeBlocks>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SpongeBlocks {
+    palette: BTreeMap<String, i32>,
+    data: ByteArray,
+    #[serde(default)]
+    block_entities: Vec<SpongeBlockEntity>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SpongeBlockEntity {
+    #[serde(rename = "Pos")]
+    pos: IntArray,
+    #[serde(rename = "Id")]
+    id: String,
+    /// The block entity's fields in versions 1 and 2, `Data` holding them in version 3.
+    #[serde(flatten)]
+    other: Compound,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Litematic {
+    minecraft_data_version: i32,
+    version: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sub_version: Option<i32>,
+    metadata: LitematicMetadata,
+    regions: BTreeMap<String, LitematicRegion>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct LitematicMetadata {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    author: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    region_count: i32,
+    #[serde(default)]
+    total_blocks: i32,
+    #[serde(default)]
+    total_volume: i32,
+    #[serde(default)]
+    time_created: i64,
+    #[serde(default)]
+    time_modified: i64,
+    #[serde(default)]
+    enclosing_size: Vec3,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct LitematicRegion {
+    position: Vec3,
+    /// Negative along the axes the region was selected towards negative coordinates.
+    size: Vec3,
+    block_state_palette: Vec<BlockState>,
+    block_states: LongArray,
+    #[serde(default)]
+    tile_entities: Vec<Compound>,
+    #[serde(default)]
+    entities: Vec<Compound>,
+    #[serde(default)]
+    pending_block_ticks: Vec<Compound>,
+    #[serde(default)]
+    pending_fluid_ticks: Vec<Compound>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct Vec3 {
+    x: i32,
+    y: i32,
+    z: i32,
+}
+
+impl Vec3 {
+    fn from_array([x, y, z]: [i32; 3]) -> Self {
+        Vec3 { x, y, z }
+    }
+
+    fn to_array(self) -> [i32; 3] {
+        [self.x, self.y, self.z]
+    }
+}
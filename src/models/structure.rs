@@ -0,0 +1,248 @@
+//! Structure templates, as saved by structure blocks to `generated/<namespace>/structures/` and
+//! shipped in data packs.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::chunk::BlockState;
+use crate::{
+    de::from_gzip_reader,
+    error::{ErrorKind, Result},
+    ser::to_gzip_writer,
+    value::Compound,
+};
+
+/// A structure template.
+///
+/// Templates usually have a single `palette`. Some, like shipwrecks, have several `palettes` of
+/// the same length instead, one of which is picked when the structure is placed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Structure {
+    #[serde(rename = "DataVersion")]
+    pub data_version: i32,
+    pub size: [i32; 3],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub palette: Option<Vec<BlockState>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub palettes: Option<Vec<Vec<BlockState>>>,
+    /// The blocks, positions without one are left as they are when the structure is placed.
+    #[serde(default)]
+    pub blocks: Vec<StructureBlock>,
+    #[serde(default)]
+    pub entities: Vec<StructureEntity>,
+}
+
+/// A block of a template, with an index into the palette.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StructureBlock {
+    pub pos: [i32; 3],
+    pub state: i32,
+    /// The block entity, without its position.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nbt: Option<Compound>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StructureEntity {
+    pub pos: [f64; 3],
+    #[serde(rename = "blockPos")]
+    pub block_pos: [i32; 3],
+    /// The entity, without its position and UUID.
+    pub nbt: Compound,
+}
+
+impl Structure {
+    /// An empty template with a single palette.
+    pub fn new(data_version: i32, size: [i32; 3]) -> Self {
+        Structure {
+            data_version,
+            size,
+            palette: Some(Vec::new()),
+            palettes: None,
+            blocks: Vec::new(),
+            entities: Vec::new(),
+        }
+    }
+
+    /// Reads a template, gzipped or not.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        from_gzip_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Writes a gzipped template, like the game does.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        to_gzip_writer(BufWriter::new(File::create(path)?), self, "")
+    }
+
+    /// Places `state` at `pos`, see [`Structure::set_block`].
+    ///
+    /// Panics if `pos` is out of bounds.
+    pub fn with_block(mut self, pos: [i32; 3], state: BlockState) -> Self {
+        if let Err(e) = self.set_block(pos, state, None) {
+            panic!("{}", e);
+        }
+        self
+    }
+
+    /// Places `state` at `pos` with a block entity, see [`Structure::set_block`].
+    ///
+    /// Panics if `pos` is out of bounds.
+    pub fn with_block_entity(mut self, pos: [i32; 3], state: BlockState, nbt: Compound) -> Self {
+        if let Err(e) = self.set_block(pos, state, Some(nbt)) {
+            panic!("{}", e);
+        }
+        self
+    }
+
+    /// Adds an entity at `pos`, in the block that contains it.
+    pub fn with_entity(mut self, pos: [f64; 3], nbt: Compound) -> Self {
+        self.entities.push(StructureEntity {
+            pos,
+            block_pos: pos.map(|c| c.floor() as i32),
+            nbt,
+        });
+        self
+    }
+
+    /// Whether the template has several `palettes` instead of a single `palette`.
+    pub fn is_multi_palette(&self) -> bool {
+        self.palettes.is_some()
+    }
+
+    /// The number of palettes.
+    pub fn palette_count(&self) -> usize {
+        match (&self.palette, &self.palettes) {
+            (_, Some(palettes)) => palettes.len(),
+            (Some(_), None) => 1,
+            (None, None) => 0,
+        }
+    }
+
+    /// The palette at `index`. A single palette has index 0.
+    pub fn palette(&self, index: usize) -> Option<&[BlockState]> {
+        match (&self.palette, &self.palettes) {
+            (_, Some(palettes)) => palettes.get(index).map(Vec::as_slice),
+            (Some(palette), None) if index == 0 => Some(palette),
+            _ => None,
+        }
+    }
+
+    /// The block at `pos`, if the template has one there.
+    pub fn block(&self, pos: [i32; 3]) -> Option<&StructureBlock> {
+        self.blocks.iter().find(|block| block.pos == pos)
+    }
+
+    /// The state of the block at `pos`, from the first palette.
+    pub fn block_at(&self, pos: [i32; 3]) -> Option<&BlockState> {
+        self.block_in_palette(0, pos)
+    }
+
+    /// The state of the block at `pos`, from the palette at `palette`.
+    pub fn block_in_palette(&self, palette: usize, pos: [i32; 3]) -> Option<&BlockState> {
+        let state = usize::try_from(self.block(pos)?.state).ok()?;
+        self.palette(palette)?.get(state)
+    }
+
+    /// Places `state` at `pos`, replacing the block there, and grows `size` to contain it if
+    /// needed.
+    ///
+    /// A state missing from the palette is added to it. With several palettes it is added to
+    /// all of them, unless every palette already has it at the same index, so the block is the
+    /// same whichever palette is picked.
+    ///
+    /// Fails with [`ErrorKind::OutOfBounds`] if a coordinate of `pos` is negative or `i32::MAX`,
+    /// leaving the structure unchanged.
+    pub fn set_block(
+        &mut self,
+        pos: [i32; 3],
+        state: BlockState,
+        nbt: Option<Compound>,
+    ) -> Result<()> {
+        let mut size = self.size;
+        for (size, coordinate) in size.iter_mut().zip(pos) {
+            match coordinate.checked_add(1) {
+                Some(end) if coordinate >= 0 => *size = (*size).max(end),
+                _ => return Err(ErrorKind::OutOfBounds(pos).into()),
+            }
+        }
+        let index = self.state_index(state)?;
+        self.size = size;
+
+        let block = StructureBlock {
+            pos,
+            state: index,
+            nbt,
+        };
+        match self.blocks.iter_mut().find(|block| block.pos == pos) {
+            Some(existing) => *existing = block,
+            None => self.blocks.push(block),
+        }
+        Ok(())
+    }
+
+    /// Removes the block at `pos`, returning whether there was one. The palette is left as is.
+    pub fn remove_block(&mut self, pos: [i32; 3]) -> bool {
+        let len = self.blocks.len();
+        self.blocks.retain(|block| block.pos != pos);
+        self.blocks.len() != len
+    }
+
+    /// Turns a single `palette` into `palettes` holding just that one.
+    pub fn into_multi_palette(mut self) -> Self {
+        if let Some(palette) = self.palette.take() {
+            self.palettes = Some(vec![palette]);
+        }
+        self
+    }
+
+    /// Keeps only the palette at `index`, as a single `palette`, or gives the structure back
+    /// unchanged if there is no palette at `index`.
+    pub fn into_single_palette(mut self, index: usize) -> std::result::Result<Self, Self> {
+        if index >= self.palette_count() {
+            return Err(self);
+        }
+
+        let palette = match (self.palette.take(), self.palettes.take()) {
+            (_, Some(mut palettes)) => palettes.swap_remove(index),
+            (palette, None) => palette.unwrap_or_default(),
+        };
+        self.palette = Some(palette);
+        Ok(self)
+    }
+
+    /// The index of `state` in the palettes, adding it if it is missing.
+    ///
+    /// With several palettes the index has to hold `state` in all of them, otherwise it is
+    /// appended to each, after padding the shorter ones so they all get it at the same index.
+    fn state_index(&mut self, state: BlockState) -> Result<i32> {
+        let palettes = match (&mut self.palette, &mut self.palettes) {
+            (_, Some(palettes)) => {
+                if palettes.is_empty() {
+                    palettes.push(Vec::new());
+                }
+                palettes.as_mut_slice()
+            }
+            (palette, None) => std::slice::from_mut(palette.get_or_insert_with(Vec::new)),
+        };
+
+        let shared = palettes[0].iter().enumerate().position(|(index, s)| {
+            *s == state && palettes[1..].iter().all(|p| p.get(index) == Some(&state))
+        });
+        let index = match shared {
+            Some(index) => index,
+            None => {
+                let index = palettes.iter().map(Vec::len).max().unwrap_or(0);
+                i32::try_from(index)?;
+                for palette in palettes.iter_mut() {
+                    palette.resize(index + 1, state.clone());
+                }
+                index
+            }
+        };
+
+        Ok(index as i32)
+    }
+}
@@ -1,3 +1,4 @@
+use std::fmt;
 
 pub(crate) const TAG_END: u8 = 0;
 pub(crate) const TAG_BYTE: u8 = 1;
@@ -13,6 +14,89 @@ pub(crate) const TAG_COMPOUND: u8 = 10;
 pub(crate) const TAG_INT_ARRAY: u8 = 11;
 pub(crate) const TAG_LONG_ARRAY: u8 = 12;
 
+/// Newtype struct names marking a sequence as `TAG_Int_Array` or `TAG_Long_Array` rather than a
+/// `TAG_List`, see `Value`. When deserializing, arrays are handed to `deserialize_any` visitors
+/// as a map with this single key.
+pub(crate) const INT_ARRAY_TOKEN: &str = "__serde_nbt_int_array";
+pub(crate) const LONG_ARRAY_TOKEN: &str = "__serde_nbt_long_array";
 
-/*
-*/
+/// The type of an NBT value, as stored in front of every named value and list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Tag {
+    End = TAG_END,
+    Byte = TAG_BYTE,
+    Short = TAG_SHORT,
+    Int = TAG_INT,
+    Long = TAG_LONG,
+    Float = TAG_FLOAT,
+    Double = TAG_DOUBLE,
+    ByteArray = TAG_BYTE_ARRAY,
+    String = TAG_STRING,
+    List = TAG_LIST,
+    Compound = TAG_COMPOUND,
+    IntArray = TAG_INT_ARRAY,
+    LongArray = TAG_LONG_ARRAY,
+}
+
+impl TryFrom<u8> for Tag {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            TAG_END => Tag::End,
+            TAG_BYTE => Tag::Byte,
+            TAG_SHORT => Tag::Short,
+            TAG_INT => Tag::Int,
+            TAG_LONG => Tag::Long,
+            TAG_FLOAT => Tag::Float,
+            TAG_DOUBLE => Tag::Double,
+            TAG_BYTE_ARRAY => Tag::ByteArray,
+            TAG_STRING => Tag::String,
+            TAG_LIST => Tag::List,
+            TAG_COMPOUND => Tag::Compound,
+            TAG_INT_ARRAY => Tag::IntArray,
+            TAG_LONG_ARRAY => Tag::LongArray,
+            other => return Err(other),
+        })
+    }
+}
+
+impl From<Tag> for u8 {
+    fn from(value: Tag) -> Self {
+        value as u8
+    }
+}
+
+impl Tag {
+    /// The name used for the tag in the NBT specification, e.g. `TAG_Byte_Array`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Tag::End => "TAG_End",
+            Tag::Byte => "TAG_Byte",
+            Tag::Short => "TAG_Short",
+            Tag::Int => "TAG_Int",
+            Tag::Long => "TAG_Long",
+            Tag::Float => "TAG_Float",
+            Tag::Double => "TAG_Double",
+            Tag::ByteArray => "TAG_Byte_Array",
+            Tag::String => "TAG_String",
+            Tag::List => "TAG_List",
+            Tag::Compound => "TAG_Compound",
+            Tag::IntArray => "TAG_Int_Array",
+            Tag::LongArray => "TAG_Long_Array",
+        }
+    }
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Name of the tag with the given id, for diagnostics where the id hasn't been validated yet.
+#[cfg(feature = "tracing")]
+pub(crate) fn tag_name(id: u8) -> &'static str {
+    Tag::try_from(id).map_or("unknown", Tag::name)
+}
@@ -0,0 +1,71 @@
+/// How unsigned Rust integers are mapped onto NBT's signed integer tags.
+///
+/// The same policy has to be used for writing and reading a file, otherwise the tags written by
+/// the [`Serializer`](crate::Serializer) won't match the ones expected by the
+/// [`Deserializer`](crate::Deserializer).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnsignedPolicy {
+    /// Store the value in the next wider signed tag (`u16` -> `TAG_Int`, `u32` -> `TAG_Long`).
+    /// `u64` is stored as `TAG_Long` and fails above `i64::MAX`.
+    ///
+    /// `u8` is the exception and stays a `TAG_Byte` holding its bits, like under
+    /// [`Reinterpret`](UnsignedPolicy::Reinterpret), because earlier versions of this crate wrote
+    /// it that way while widening the others. Files they wrote load with this policy.
+    #[default]
+    Widen,
+    /// Reinterpret the bits into the signed tag of the same width (`u8` -> `TAG_Byte`, `u16` ->
+    /// `TAG_Short`, `u32` -> `TAG_Int`, `u64` -> `TAG_Long`). This is what Java code sees when it
+    /// reads the value back.
+    Reinterpret,
+    /// Refuse to write or read unsigned integers.
+    Reject,
+}
+
+/// Which tags the [`Deserializer`](crate::Deserializer) accepts for numbers and booleans.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Coercion {
+    /// Every number has to be stored in exactly the tag its type is written as.
+    #[default]
+    Strict,
+    /// Accept any integer tag whose value fits into the requested integer type, either float tag
+    /// for `f32`/`f64`, and integer `0`/`1` for `bool`.
+    ///
+    /// Useful for files written by different game versions which store the same field as byte,
+    /// short or int.
+    Lenient,
+}
+
+/// Resource limits the [`Deserializer`](crate::Deserializer) enforces while reading untrusted
+/// input. Exceeding one of them fails with a typed error before anything is allocated for the
+/// offending value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// How deep compounds and lists may be nested. Vanilla uses 512.
+    pub max_depth: usize,
+    /// How many bytes may be read in total, `None` for no limit.
+    pub max_bytes: Option<u64>,
+    /// How many elements a single list or array may have, `None` for no limit.
+    pub max_len: Option<u64>,
+}
+
+impl Limits {
+    /// The limits vanilla applies to NBT received over the network: a nesting depth of 512 and a
+    /// quota of 2 MiB.
+    pub fn network() -> Self {
+        Self {
+            max_bytes: Some(2 * 1024 * 1024),
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for Limits {
+    /// The vanilla nesting depth of 512, without size limits.
+    fn default() -> Self {
+        Self {
+            max_depth: 512,
+            max_bytes: None,
+            max_len: None,
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! Small unsigned integers bit-packed into longs, like the palette indices of block states and
+//! biomes or the heights in heightmaps.
+
+use crate::{
+    array::LongArray,
+    error::{ErrorKind, Result},
+};
+
+/// How entries are laid out in the longs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Packing {
+    /// Each long holds as many whole entries as fit, starting at the lowest bits. The remaining
+    /// high bits are unused. Used since 1.16.
+    Aligned,
+    /// Entries follow each other without gaps, so an entry may start in one long and end in the
+    /// next. Used before 1.16.
+    Spanning,
+}
+
+/// A fixed number of entries, each `bits_per_entry` bits wide, packed into longs.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PackedArray {
+    longs: Vec<i64>,
+    len: usize,
+    bits: u32,
+    packing: Packing,
+}
+
+impl PackedArray {
+    /// Creates an array of `len` zeros.
+    ///
+    /// Panics unless `bits_per_entry` is between 1 and 32.
+    pub fn new(len: usize, bits_per_entry: u32, packing: Packing) -> Self {
+        assert!(
+            (1..=32).contains(&bits_per_entry),
+            "bits per entry must be between 1 and 32, got {}",
+            bits_per_entry
+        );
+
+        PackedArray {
+            longs: vec![0; long_count(len, bits_per_entry, packing)],
+            len,
+            bits: bits_per_entry,
+            packing,
+        }
+    }
+
+    /// Packs `values`, panicking if one of them doesn't fit into `bits_per_entry` bits.
+    pub fn from_values(values: &[u32], bits_per_entry: u32, packing: Packing) -> Self {
+        let mut array = PackedArray::new(values.len(), bits_per_entry, packing);
+        for (i, &value) in values.iter().enumerate() {
+            array.set(i, value);
+        }
+
+        array
+    }
+
+    /// Takes `len` entries from the longs of a `TAG_Long_Array`, failing with
+    /// [`ErrorKind::ListLengthMismatch`] if there are more or fewer longs than they need.
+    ///
+    /// Panics unless `bits_per_entry` is between 1 and 32.
+    pub fn from_long_array(
+        array: LongArray,
+        len: usize,
+        bits_per_entry: u32,
+        packing: Packing,
+    ) -> Result<Self> {
+        let mut packed = PackedArray::new(0, bits_per_entry, packing);
+        let expected = long_count(len, bits_per_entry, packing);
+        if array.len() != expected {
+            return Err(ErrorKind::ListLengthMismatch {
+                expected,
+                found: array.len(),
+            }
+            .into());
+        }
+
+        packed.longs = array.0;
+        packed.len = len;
+        Ok(packed)
+    }
+
+    pub fn bits_per_entry(&self) -> u32 {
+        self.bits
+    }
+
+    pub fn packing(&self) -> Packing {
+        self.packing
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<u32> {
+        if index >= self.len {
+            return None;
+        }
+
+        read(&self.longs, index, self.bits, self.packing)
+    }
+
+    /// Panics if `index` is out of bounds or `value` doesn't fit into `bits_per_entry` bits.
+    pub fn set(&mut self, index: usize, value: u32) {
+        assert!(
+            index < self.len,
+            "index {} out of bounds for length {}",
+            index,
+            self.len
+        );
+        let mask = self.mask();
+        assert!(
+            u64::from(value) <= mask,
+            "{} doesn't fit into {} bits",
+            value,
+            self.bits
+        );
+
+        let value = u64::from(value);
+        let (long, shift) = position(index, self.bits, self.packing);
+        let low = self.longs[long] as u64 & !(mask << shift) | value << shift;
+        self.longs[long] = low as i64;
+        if shift + self.bits as usize > 64 {
+            let high = self.longs[long + 1] as u64 & !(mask >> (64 - shift));
+            self.longs[long + 1] = (high | value >> (64 - shift)) as i64;
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..self.len).map(|i| self.get(i).unwrap())
+    }
+
+    /// Copies the entries into a new array with a different width or layout, e.g. after the
+    /// palette grew. Panics if an entry doesn't fit into `bits_per_entry` bits.
+    pub fn repack(&self, bits_per_entry: u32, packing: Packing) -> PackedArray {
+        let mut array = PackedArray::new(self.len, bits_per_entry, packing);
+        for (i, value) in self.iter().enumerate() {
+            array.set(i, value);
+        }
+
+        array
+    }
+
+    /// The longs as they are stored in a `TAG_Long_Array`.
+    pub fn into_long_array(self) -> LongArray {
+        LongArray(self.longs)
+    }
+
+    fn mask(&self) -> u64 {
+        (1 << self.bits) - 1
+    }
+}
+
+impl From<PackedArray> for LongArray {
+    fn from(array: PackedArray) -> Self {
+        array.into_long_array()
+    }
+}
+
+/// Reads entry `index` straight from `longs`, or `None` if they are too short to hold it.
+pub(crate) fn read(longs: &[i64], index: usize, bits: u32, packing: Packing) -> Option<u32> {
+    let (long, shift) = position(index, bits, packing);
+    let mut value = *longs.get(long)? as u64 >> shift;
+    if shift + bits as usize > 64 {
+        value |= (*longs.get(long + 1)? as u64) << (64 - shift);
+    }
+
+    Some((value & ((1 << bits) - 1)) as u32)
+}
+
+/// The long an entry starts in and the entry's offset in it.
+fn position(index: usize, bits: u32, packing: Packing) -> (usize, usize) {
+    match packing {
+        Packing::Aligned => {
+            let per_long = 64 / bits as usize;
+            (index / per_long, index % per_long * bits as usize)
+        }
+        Packing::Spanning => {
+            let bit = index * bits as usize;
+            (bit / 64, bit % 64)
+        }
+    }
+}
+
+/// How many longs `len` entries of `bits` bits take up.
+fn long_count(len: usize, bits: u32, packing: Packing) -> usize {
+    match packing {
+        Packing::Aligned => len.div_ceil(64 / bits as usize),
+        Packing::Spanning => (len * bits as usize).div_ceil(64),
+    }
+}
@@ -0,0 +1,677 @@
+//! Paths into a [`Value`] in the syntax of Minecraft's `/data` command, e.g.
+//! `Inventory[{Slot:0b}].tag.display.Name` or `Items[-1]`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{
+    error::{Error, ErrorKind, Result},
+    value::{remove_entry, Compound, Value},
+};
+
+/// A parsed NBT path.
+///
+/// A path is made of these nodes:
+///
+/// - `key` or `"quoted key"`, an entry of a compound, separated from the previous node by a dot
+/// - `key{Filter:1b}`, an entry of a compound that matches the filter
+/// - `{Filter:1b}`, at the start only, the root compound if it matches the filter
+/// - `[3]` or `[-1]`, an element of a list counted from the front or the back
+/// - `[]`, all elements of a list
+/// - `[{Filter:1b}]`, all compound elements of a list that match the filter
+///
+/// Filters are written in SNBT, and a value matches a filter if it has all of the filter's
+/// entries. Lists in filters match if each of their elements matches any element of the value.
+///
+/// Queries fail with [`ErrorKind::NoMatch`] naming the part of the path that matched nothing.
+#[derive(Debug, Clone)]
+pub struct NbtPath {
+    source: String,
+    /// Each node with the offset in `source` where it ends.
+    nodes: Vec<(Node, usize)>,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    MatchRoot(Compound),
+    Key(String, Option<Compound>),
+    Index(i32),
+    All,
+    MatchElement(Compound),
+}
+
+impl NbtPath {
+    pub fn parse(path: &str) -> Result<Self> {
+        let mut parser = Parser { src: path, pos: 0 };
+        let mut nodes = Vec::new();
+
+        loop {
+            let start = parser.pos;
+            let node = match parser.peek() {
+                None if nodes.is_empty() => Err(parser.invalid(start)),
+                None => break,
+                Some('[') => parser.bracket(),
+                Some('{') if nodes.is_empty() => parser.compound().map(Node::MatchRoot),
+                Some('.') if !nodes.is_empty() => {
+                    parser.pos += 1;
+                    parser.key_node()
+                }
+                _ if nodes.is_empty() => parser.key_node(),
+                _ => Err(parser.invalid(start)),
+            };
+            // errors inside a filter name the whole node rather than a piece of it
+            let node = node.map_err(|_| parser.invalid(start))?;
+            nodes.push((node, parser.pos));
+        }
+
+        Ok(NbtPath {
+            source: path.to_string(),
+            nodes,
+        })
+    }
+
+    /// Returns the first value the path matches.
+    pub fn get<'a>(&self, root: &'a Value) -> Result<&'a Value> {
+        Ok(self.select(&self.nodes, root)?[0])
+    }
+
+    /// Returns all values the path matches, in order.
+    pub fn get_all<'a>(&self, root: &'a Value) -> Result<Vec<&'a Value>> {
+        self.select(&self.nodes, root)
+    }
+
+    /// Replaces every value the path matches with `value`, and returns how many there were.
+    ///
+    /// If the last node is a plain key, the entry is created if it's missing, as are compounds
+    /// for plain keys on the way there. Elements of lists can only be replaced by values of the
+    /// list's type.
+    ///
+    /// If the set fails or matches nothing, `root` is left as it was.
+    pub fn set(&self, root: &mut Value, value: Value) -> Result<usize> {
+        let Some(((last, _), parents)) = self.nodes.split_last() else {
+            return Ok(0);
+        };
+
+        let targets = self.select_mut(parents, root, true)?;
+        // check everything up front, so that nothing is changed if a value doesn't fit
+        for target in targets.iter().filter(|target| target.missing.is_empty()) {
+            check_set(last, target.value, &value)?;
+        }
+
+        let mut count = 0;
+        for Target {
+            value: parent,
+            missing,
+        } in targets
+        {
+            if let Some((first, rest)) = missing.split_first() {
+                // only a plain key can be set in a compound that has yet to be created
+                if let (Node::Key(key, None), Value::Compound(compound)) = (last, parent) {
+                    let mut entry = [(key.clone(), value.clone())].into_iter().collect();
+                    for key in rest.iter().rev() {
+                        entry = [(key.clone(), Value::Compound(entry))]
+                            .into_iter()
+                            .collect();
+                    }
+                    compound.insert(first.clone(), Value::Compound(entry));
+                    count += 1;
+                }
+                continue;
+            }
+
+            match (last, parent) {
+                (Node::MatchRoot(filter), parent) if matches_compound(filter, parent) => {
+                    *parent = value.clone();
+                    count += 1;
+                }
+                (Node::Key(key, None), Value::Compound(compound)) => {
+                    compound.insert(key.clone(), value.clone());
+                    count += 1;
+                }
+                (Node::Key(key, Some(filter)), Value::Compound(compound)) => {
+                    if let Some(entry) = compound.get_mut(key) {
+                        if matches_compound(filter, entry) {
+                            *entry = value.clone();
+                            count += 1;
+                        }
+                    }
+                }
+                (Node::Index(index), Value::List(list)) => {
+                    if let Some(i) = resolve(*index, list.len()) {
+                        list[i] = value.clone();
+                        count += 1;
+                    }
+                }
+                (Node::All, Value::List(list)) => {
+                    for element in list.iter_mut() {
+                        *element = value.clone();
+                        count += 1;
+                    }
+                }
+                (Node::MatchElement(filter), Value::List(list)) => {
+                    for element in list.iter_mut() {
+                        if matches_compound(filter, element) {
+                            *element = value.clone();
+                            count += 1;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        self.matched(count)
+    }
+
+    /// Removes every value the path matches and returns how many there were.
+    pub fn remove(&self, root: &mut Value) -> Result<usize> {
+        let Some(((last, _), parents)) = self.nodes.split_last() else {
+            return Ok(0);
+        };
+
+        let mut count = 0;
+        for Target { value: parent, .. } in self.select_mut(parents, root, false)? {
+            match (last, parent) {
+                (Node::Key(key, filter), Value::Compound(compound)) => {
+                    let matched = compound
+                        .get(key)
+                        .is_some_and(|entry| matches_filter(filter, entry));
+                    if matched {
+                        remove_entry(compound, key);
+                        count += 1;
+                    }
+                }
+                (Node::Index(index), Value::List(list)) => {
+                    if let Some(i) = resolve(*index, list.len()) {
+                        list.remove(i);
+                        count += 1;
+                    }
+                }
+                (Node::All, Value::List(list)) => {
+                    count += list.len();
+                    list.clear();
+                }
+                (Node::MatchElement(filter), Value::List(list)) => {
+                    let len = list.len();
+                    list.retain(|element| !matches_compound(filter, element));
+                    count += len - list.len();
+                }
+                // the root can't be removed
+                _ => {}
+            }
+        }
+
+        self.matched(count)
+    }
+
+    /// Inserts `value` into every list the path matches, and returns how many there were.
+    ///
+    /// Negative indices count from the back, so `-1` appends to the list.
+    pub fn insert(&self, root: &mut Value, index: i32, value: Value) -> Result<usize> {
+        let mut count = 0;
+        for Target { value: target, .. } in self.select_mut(&self.nodes, root, false)? {
+            if let Value::List(list) = target {
+                let len = list.len() as i64;
+                let i = if index < 0 {
+                    len + 1 + index as i64
+                } else {
+                    index as i64
+                };
+                if (0..=len).contains(&i) {
+                    check_element(list, &value)?;
+                    list.insert(i as usize, value.clone());
+                    count += 1;
+                }
+            }
+        }
+
+        self.matched(count)
+    }
+
+    fn select<'a>(&self, nodes: &[(Node, usize)], root: &'a Value) -> Result<Vec<&'a Value>> {
+        let mut current = vec![root];
+        for (node, end) in nodes {
+            let mut next = Vec::new();
+            for value in current {
+                match (node, value) {
+                    (Node::MatchRoot(filter), value) if matches_compound(filter, value) => {
+                        next.push(value);
+                    }
+                    (Node::Key(key, filter), Value::Compound(compound)) => {
+                        next.extend(
+                            compound
+                                .get(key)
+                                .filter(|entry| matches_filter(filter, entry)),
+                        );
+                    }
+                    (Node::Index(index), Value::List(list)) => {
+                        next.extend(resolve(*index, list.len()).map(|i| &list[i]));
+                    }
+                    (Node::All, Value::List(list)) => next.extend(list),
+                    (Node::MatchElement(filter), Value::List(list)) => {
+                        next.extend(list.iter().filter(|e| matches_compound(filter, e)));
+                    }
+                    _ => {}
+                }
+            }
+            if next.is_empty() {
+                return Err(self.no_match(*end));
+            }
+            current = next;
+        }
+
+        Ok(current)
+    }
+
+    /// Like `select`, but with `create` a missing plain key leads to a compound that `set` may
+    /// create later, recorded in the target's `missing` keys.
+    fn select_mut<'a>(
+        &self,
+        nodes: &[(Node, usize)],
+        root: &'a mut Value,
+        create: bool,
+    ) -> Result<Vec<Target<'a>>> {
+        let mut current = vec![Target::new(root)];
+        for (node, end) in nodes {
+            let mut next = Vec::new();
+            for Target { value, mut missing } in current {
+                let absent = match (node, &*value) {
+                    (Node::Key(key, None), Value::Compound(compound)) => {
+                        create && compound.get(key).is_none()
+                    }
+                    _ => false,
+                };
+                // below a missing key there is only an empty compound, so only plain keys go on
+                if absent || !missing.is_empty() {
+                    if let Node::Key(key, None) = node {
+                        missing.push(key.clone());
+                        next.push(Target { value, missing });
+                    }
+                    continue;
+                }
+
+                match (node, value) {
+                    (Node::MatchRoot(filter), value) if matches_compound(filter, value) => {
+                        next.push(Target::new(value));
+                    }
+                    (Node::Key(key, filter), Value::Compound(compound)) => {
+                        next.extend(
+                            compound
+                                .get_mut(key)
+                                .filter(|entry| matches_filter(filter, entry))
+                                .map(Target::new),
+                        );
+                    }
+                    (Node::Index(index), Value::List(list)) => {
+                        if let Some(i) = resolve(*index, list.len()) {
+                            next.push(Target::new(&mut list[i]));
+                        }
+                    }
+                    (Node::All, Value::List(list)) => next.extend(list.iter_mut().map(Target::new)),
+                    (Node::MatchElement(filter), Value::List(list)) => {
+                        next.extend(
+                            list.iter_mut()
+                                .filter(|e| matches_compound(filter, e))
+                                .map(Target::new),
+                        );
+                    }
+                    _ => {}
+                }
+            }
+            if next.is_empty() {
+                return Err(self.no_match(*end));
+            }
+            current = next;
+        }
+
+        Ok(current)
+    }
+
+    fn matched(&self, count: usize) -> Result<usize> {
+        match count {
+            0 => Err(self.no_match(self.source.len())),
+            count => Ok(count),
+        }
+    }
+
+    fn no_match(&self, end: usize) -> Error {
+        ErrorKind::NoMatch(self.source[..end].to_string()).into()
+    }
+}
+
+impl FromStr for NbtPath {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        NbtPath::parse(s)
+    }
+}
+
+impl fmt::Display for NbtPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// A value reached by `select_mut`.
+struct Target<'a> {
+    value: &'a mut Value,
+    /// Plain keys below `value` that don't exist yet, each a compound to be created in the
+    /// previous one.
+    missing: Vec<String>,
+}
+
+impl<'a> Target<'a> {
+    fn new(value: &'a mut Value) -> Self {
+        Target {
+            value,
+            missing: Vec::new(),
+        }
+    }
+}
+
+/// Fails if setting the `last` node of a path in `parent` would put `value` into a list of
+/// another type.
+fn check_set(last: &Node, parent: &Value, value: &Value) -> Result<()> {
+    match (last, parent) {
+        (Node::Index(index), Value::List(list)) if list.len() > 1 => {
+            match resolve(*index, list.len()) {
+                Some(_) => check_element(list, value),
+                None => Ok(()),
+            }
+        }
+        (Node::MatchElement(filter), Value::List(list)) => list
+            .iter()
+            .filter(|element| matches_compound(filter, element))
+            .try_for_each(|element| check_element(std::slice::from_ref(element), value)),
+        _ => Ok(()),
+    }
+}
+
+/// Index into a list of `len` elements, negative indices counting from the back.
+fn resolve(index: i32, len: usize) -> Option<usize> {
+    let i = if index < 0 {
+        len as i64 + index as i64
+    } else {
+        index as i64
+    };
+
+    (0..len as i64).contains(&i).then_some(i as usize)
+}
+
+/// Fails if `value` can't be stored in `list`, whose elements all share a tag.
+fn check_element(list: &[Value], value: &Value) -> Result<()> {
+    match list.first() {
+        Some(first) if first.tag() != value.tag() => Err(Error::incompatible_list_type(
+            first.tag().into(),
+            value.tag().into(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Whether `value` passes an optional `key{filter}`.
+fn matches_filter(filter: &Option<Compound>, value: &Value) -> bool {
+    match filter {
+        Some(filter) => matches_compound(filter, value),
+        None => true,
+    }
+}
+
+fn matches_compound(filter: &Compound, value: &Value) -> bool {
+    match value {
+        Value::Compound(compound) => filter.iter().all(|(key, filter)| {
+            compound
+                .get(key)
+                .is_some_and(|value| matches(filter, value))
+        }),
+        _ => false,
+    }
+}
+
+fn matches(filter: &Value, value: &Value) -> bool {
+    match (filter, value) {
+        (Value::Compound(filter), value) => matches_compound(filter, value),
+        (Value::List(filter), Value::List(list)) if !filter.is_empty() => filter
+            .iter()
+            .all(|filter| list.iter().any(|value| matches(filter, value))),
+        _ => filter == value,
+    }
+}
+
+/// Parses paths and the SNBT filters inside of them.
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.src[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char, start: usize) -> Result<()> {
+        match self.eat(c) {
+            true => Ok(()),
+            false => Err(self.invalid(start)),
+        }
+    }
+
+    /// An error naming the node starting at `start`, up to the next dot after the failure.
+    fn invalid(&self, start: usize) -> Error {
+        let end = self.src[self.pos..]
+            .find('.')
+            .map_or(self.src.len(), |i| self.pos + i);
+
+        ErrorKind::InvalidPath(self.src[start..end.max(self.pos)].to_string()).into()
+    }
+
+    /// `key` or `key{filter}`.
+    fn key_node(&mut self) -> Result<Node> {
+        let key = self.key(|c| !" .[]{}\"'".contains(c))?;
+        let filter = match self.peek() {
+            Some('{') => Some(self.compound()?),
+            _ => None,
+        };
+
+        Ok(Node::Key(key, filter))
+    }
+
+    /// `[index]`, `[]` or `[{filter}]`.
+    fn bracket(&mut self) -> Result<Node> {
+        let start = self.pos;
+        self.expect('[', start)?;
+        self.skip_whitespace();
+
+        let node = match self.peek() {
+            Some(']') => Node::All,
+            Some('{') => Node::MatchElement(self.compound()?),
+            _ => {
+                let token = self.unquoted(|c| c == '-' || c.is_ascii_digit());
+                Node::Index(token.parse().map_err(|_| self.invalid(start))?)
+            }
+        };
+        self.expect(']', start)?;
+
+        Ok(node)
+    }
+
+    /// A quoted string, or an unquoted one made of characters accepted by `allowed`.
+    fn key(&mut self, allowed: fn(char) -> bool) -> Result<String> {
+        let start = self.pos;
+        match self.peek() {
+            Some(quote @ ('"' | '\'')) => self.quoted(quote, start),
+            _ => match self.unquoted(allowed) {
+                "" => Err(self.invalid(start)),
+                key => Ok(key.to_string()),
+            },
+        }
+    }
+
+    fn unquoted(&mut self, allowed: fn(char) -> bool) -> &str {
+        let start = self.pos;
+        let len = self.src[start..]
+            .find(|c| !allowed(c))
+            .unwrap_or(self.src.len() - start);
+        self.pos += len;
+
+        &self.src[start..self.pos]
+    }
+
+    fn quoted(&mut self, quote: char, start: usize) -> Result<String> {
+        self.pos += 1;
+        let mut s = String::new();
+        let mut chars = self.src[self.pos..].chars();
+        loop {
+            let c = chars.next().ok_or_else(|| self.invalid(start))?;
+            self.pos += c.len_utf8();
+            match c {
+                '\\' => {
+                    let escaped = chars.next().ok_or_else(|| self.invalid(start))?;
+                    self.pos += escaped.len_utf8();
+                    s.push(escaped);
+                }
+                c if c == quote => return Ok(s),
+                c => s.push(c),
+            }
+        }
+    }
+
+    /// An SNBT compound, e.g. `{Slot:0b,id:"minecraft:stone"}`.
+    fn compound(&mut self) -> Result<Compound> {
+        let start = self.pos;
+        self.expect('{', start)?;
+
+        let mut compound = Compound::new();
+        if self.eat('}') {
+            return Ok(compound);
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.key(is_snbt_char)?;
+            self.expect(':', start)?;
+            let value = self.value()?;
+            compound.insert(key, value);
+
+            if self.eat('}') {
+                return Ok(compound);
+            }
+            self.expect(',', start)?;
+        }
+    }
+
+    /// Any SNBT value.
+    fn value(&mut self) -> Result<Value> {
+        self.skip_whitespace();
+        let start = self.pos;
+        match self.peek() {
+            Some('{') => Ok(Value::Compound(self.compound()?)),
+            Some('[') => self.list(),
+            Some(quote @ ('"' | '\'')) => Ok(Value::String(self.quoted(quote, start)?)),
+            _ => match self.unquoted(is_snbt_char) {
+                "" => Err(self.invalid(start)),
+                token => Ok(scalar(token)),
+            },
+        }
+    }
+
+    /// An SNBT list, or an array like `[I;1,2,3]`.
+    fn list(&mut self) -> Result<Value> {
+        let start = self.pos;
+        self.expect('[', start)?;
+
+        let array = ["B;", "I;", "L;"]
+            .into_iter()
+            .find(|prefix| self.src[self.pos..].starts_with(prefix));
+        if let Some(prefix) = array {
+            self.pos += prefix.len();
+        }
+
+        let mut elements = Vec::new();
+        if !self.eat(']') {
+            loop {
+                elements.push(self.value()?);
+                if self.eat(']') {
+                    break;
+                }
+                self.expect(',', start)?;
+            }
+        }
+
+        let integer = |value: &Value| match value {
+            Value::Byte(v) => Some(*v as i64),
+            Value::Short(v) => Some(*v as i64),
+            Value::Int(v) => Some(*v as i64),
+            Value::Long(v) => Some(*v),
+            _ => None,
+        };
+        let integers = elements.iter().map(integer);
+        let invalid = || self.invalid(start);
+        Ok(match array {
+            Some("B;") => Value::ByteArray(
+                integers
+                    .map(|v| v.and_then(|v| i8::try_from(v).ok()).map(|v| v as u8))
+                    .collect::<Option<_>>()
+                    .ok_or_else(invalid)?,
+            ),
+            Some("I;") => Value::IntArray(
+                integers
+                    .map(|v| v.and_then(|v| i32::try_from(v).ok()))
+                    .collect::<Option<_>>()
+                    .ok_or_else(invalid)?,
+            ),
+            Some(_) => Value::LongArray(integers.collect::<Option<_>>().ok_or_else(invalid)?),
+            None => {
+                if elements.windows(2).any(|w| w[0].tag() != w[1].tag()) {
+                    return Err(invalid());
+                }
+                Value::List(elements)
+            }
+        })
+    }
+}
+
+fn is_snbt_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_-.+".contains(c)
+}
+
+/// Interprets an unquoted SNBT token as a number with an optional type suffix, a boolean or
+/// else a string.
+fn scalar(token: &str) -> Value {
+    let (number, suffix) = token.split_at(token.len() - 1);
+    let parsed = match suffix {
+        "b" | "B" => number.parse().ok().map(Value::Byte),
+        "s" | "S" => number.parse().ok().map(Value::Short),
+        "l" | "L" => number.parse().ok().map(Value::Long),
+        "f" | "F" => number.parse().ok().map(Value::Float),
+        "d" | "D" => number.parse().ok().map(Value::Double),
+        _ => None,
+    };
+
+    parsed
+        .or_else(|| token.parse().ok().map(Value::Int))
+        .or_else(|| match token.contains(['.', 'e', 'E']) {
+            true => token.parse().ok().map(Value::Double),
+            false => None,
+        })
+        .unwrap_or_else(|| match token {
+            "true" => Value::Byte(1),
+            "false" => Value::Byte(0),
+            _ => Value::String(token.to_string()),
+        })
+}
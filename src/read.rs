@@ -0,0 +1,159 @@
+//! Input sources for the [`Deserializer`](crate::Deserializer).
+//!
+//! [`IoRead`] wraps any [`std::io::Read`] and copies payloads into a scratch buffer, while
+//! [`SliceRead`] hands out slices of its input so strings and byte arrays can be borrowed.
+
+use std::io;
+
+/// A source the deserializer can read from. Besides the byte-wise [`io::Read`] this allows
+/// reading whole payloads, borrowing them from the input where possible.
+pub trait Read<'de>: io::Read {
+    /// Number of bytes consumed so far.
+    fn offset(&self) -> u64;
+
+    /// Reads `len` bytes, either borrowed from the input or copied into `scratch`.
+    fn read_ref<'s>(
+        &'s mut self,
+        len: u64,
+        scratch: &'s mut Vec<u8>,
+    ) -> io::Result<Reference<'de, 's, [u8]>>;
+
+    /// Discards the next `len` bytes.
+    fn skip(&mut self, len: u64) -> io::Result<()>;
+}
+
+/// Bytes returned by [`Read::read_ref`], borrowed for `'b` if they live as long as the input or
+/// for `'c` if they had to be copied into a scratch buffer.
+pub enum Reference<'b, 'c, T: ?Sized> {
+    Borrowed(&'b T),
+    Copied(&'c T),
+}
+
+impl<T: ?Sized> std::ops::Deref for Reference<'_, '_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self {
+            Reference::Borrowed(b) => b,
+            Reference::Copied(c) => c,
+        }
+    }
+}
+
+fn unexpected_eof() -> io::Error {
+    io::Error::from(io::ErrorKind::UnexpectedEof)
+}
+
+/// Reads from an [`io::Read`], counting the bytes read so errors can report where in the stream
+/// they happened.
+pub struct IoRead<R> {
+    inner: R,
+    offset: u64,
+}
+
+impl<R: io::Read> IoRead<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, offset: 0 }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: io::Read> io::Read for IoRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.offset += n as u64;
+        Ok(n)
+    }
+}
+
+impl<'de, R: io::Read> Read<'de> for IoRead<R> {
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn read_ref<'s>(
+        &'s mut self,
+        len: u64,
+        scratch: &'s mut Vec<u8>,
+    ) -> io::Result<Reference<'de, 's, [u8]>> {
+        // let the buffer grow with the data actually read instead of trusting the length prefix
+        scratch.clear();
+        io::Read::read_to_end(&mut io::Read::take(&mut *self, len), scratch)?;
+        if (scratch.len() as u64) < len {
+            return Err(unexpected_eof());
+        }
+
+        Ok(Reference::Copied(scratch))
+    }
+
+    fn skip(&mut self, len: u64) -> io::Result<()> {
+        let skipped = io::copy(&mut io::Read::take(&mut *self, len), &mut io::sink())?;
+        if skipped < len {
+            return Err(unexpected_eof());
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads from a byte slice, lending out parts of it without copying.
+pub struct SliceRead<'de> {
+    slice: &'de [u8],
+    index: usize,
+}
+
+impl<'de> SliceRead<'de> {
+    pub fn new(slice: &'de [u8]) -> Self {
+        Self { slice, index: 0 }
+    }
+
+    /// The part of the input that hasn't been read yet.
+    pub fn remaining(&self) -> &'de [u8] {
+        &self.slice[self.index..]
+    }
+
+    fn advance(&mut self, len: u64) -> io::Result<&'de [u8]> {
+        let rest = self.remaining();
+        match usize::try_from(len) {
+            Ok(len) if len <= rest.len() => {
+                self.index += len;
+                Ok(&rest[..len])
+            }
+            _ => {
+                self.index = self.slice.len();
+                Err(unexpected_eof())
+            }
+        }
+    }
+}
+
+impl io::Read for SliceRead<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let rest = self.remaining();
+        let n = buf.len().min(rest.len());
+        buf[..n].copy_from_slice(&rest[..n]);
+        self.index += n;
+        Ok(n)
+    }
+}
+
+impl<'de> Read<'de> for SliceRead<'de> {
+    fn offset(&self) -> u64 {
+        self.index as u64
+    }
+
+    fn read_ref<'s>(
+        &'s mut self,
+        len: u64,
+        _scratch: &'s mut Vec<u8>,
+    ) -> io::Result<Reference<'de, 's, [u8]>> {
+        self.advance(len).map(Reference::Borrowed)
+    }
+
+    fn skip(&mut self, len: u64) -> io::Result<()> {
+        self.advance(len).map(|_| ())
+    }
+}
@@ -0,0 +1,312 @@
+//! Pull-based reading of NBT as a stream of [`Event`]s, for inputs too large to be deserialized
+//! into a single value.
+
+use byteorder::{BigEndian, ReadBytesExt};
+use std::io;
+
+use crate::{
+    de::Deserializer,
+    error::{ErrorKind, Result},
+    nbt::*,
+    options::Limits,
+    read::{IoRead, Read, Reference, SliceRead},
+};
+
+/// One step through an NBT tree, returned by [`NbtReader::next`].
+///
+/// Every named value is announced by a `Field` event carrying its tag and name, followed by the
+/// events of its payload. Compounds are the exception: a named compound starts with
+/// `StartCompound(Some(name))` while compounds inside lists start with `StartCompound(None)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event<'a> {
+    StartCompound(Option<&'a str>),
+    Field(Tag, &'a str),
+    EndCompound,
+    /// A list with its element tag and length, followed by the payloads of its elements.
+    StartList(Tag, usize),
+    EndList,
+    /// A byte, int or long array with its length, followed by its elements in chunks.
+    StartArray(Tag, usize),
+    ByteChunk(&'a [u8]),
+    IntChunk(&'a [i32]),
+    LongChunk(&'a [i64]),
+    EndArray,
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    String(&'a str),
+}
+
+/// The compounds, lists and arrays the reader is currently inside of.
+enum Frame {
+    Compound,
+    List { tag: u8, remaining: u64 },
+    Array { tag: u8, remaining: u64 },
+}
+
+/// Reads a single root value as a sequence of [`Event`]s.
+///
+/// Unlike the [`Deserializer`](crate::Deserializer) this never holds more than one chunk of an
+/// array in memory, and parts of the tree can be passed over with [`NbtReader::skip`].
+pub struct NbtReader<R> {
+    de: Deserializer<R>,
+    stack: Vec<Frame>,
+    /// Tag of the next payload, whose header has been returned as a `Field` event already.
+    pending: Option<u8>,
+    started: bool,
+    chunk_len: usize,
+    ints: Vec<i32>,
+    longs: Vec<i64>,
+}
+
+impl<R: io::Read> NbtReader<IoRead<R>> {
+    pub fn from_reader(reader: R) -> Self {
+        NbtReader::new(IoRead::new(reader))
+    }
+
+    /// Returns the underlying reader, positioned after the last event read.
+    pub fn into_inner(self) -> R {
+        self.de.into_inner()
+    }
+}
+
+impl<'de> NbtReader<SliceRead<'de>> {
+    /// Creates a reader whose names, strings and byte chunks are borrowed from `slice`.
+    pub fn from_slice(slice: &'de [u8]) -> Self {
+        NbtReader::new(SliceRead::new(slice))
+    }
+}
+
+impl<'de, R: Read<'de>> NbtReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            de: Deserializer::new(reader),
+            stack: Vec::new(),
+            pending: None,
+            started: false,
+            chunk_len: 4096,
+            ints: Vec::new(),
+            longs: Vec::new(),
+        }
+    }
+
+    /// Sets the resource limits for untrusted input, see [`Limits`].
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.de = self.de.with_limits(limits);
+        self
+    }
+
+    /// Sets the maximum number of array elements returned per chunk, 4096 by default.
+    pub fn with_chunk_len(mut self, len: usize) -> Self {
+        self.chunk_len = len.max(1);
+        self
+    }
+
+    /// Number of bytes consumed so far, e.g. to report where an error happened.
+    pub fn offset(&self) -> u64 {
+        self.de.reader.offset()
+    }
+
+    /// Reads the next event, or `None` once the root value has been read completely.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next<'a>(&'a mut self) -> Result<Option<Event<'a>>>
+    where
+        'de: 'a,
+    {
+        if let Some(tag) = self.pending.take() {
+            return self.payload(tag).map(Some);
+        }
+
+        match self.stack.last_mut() {
+            None if self.started => Ok(None),
+            None => {
+                self.started = true;
+                self.entry()
+            }
+            Some(Frame::Compound) => self.entry(),
+            Some(Frame::List { remaining: 0, .. }) => {
+                self.stack.pop();
+                self.de.leave();
+                Ok(Some(Event::EndList))
+            }
+            Some(Frame::List { tag, remaining }) => {
+                *remaining -= 1;
+                let tag = *tag;
+                self.payload(tag).map(Some)
+            }
+            Some(Frame::Array { remaining: 0, .. }) => {
+                self.stack.pop();
+                Ok(Some(Event::EndArray))
+            }
+            Some(Frame::Array { tag, remaining }) => {
+                let len = (*remaining).min(self.chunk_len as u64);
+                *remaining -= len;
+                let tag = *tag;
+                self.chunk(tag, len).map(Some)
+            }
+        }
+    }
+
+    /// Skips the value announced by the last `Field` event, or else the rest of the innermost
+    /// compound, list or array. No end event is returned for a container skipped this way.
+    pub fn skip(&mut self) -> Result<()> {
+        if let Some(tag) = self.pending.take() {
+            return self.de.skip_payload(tag);
+        }
+
+        match self.stack.pop() {
+            None => {}
+            Some(Frame::Compound) => {
+                // skip_payload enters the compound again
+                self.de.leave();
+                self.de.skip_payload(TAG_COMPOUND)?;
+            }
+            Some(Frame::List { tag, remaining }) => {
+                for _ in 0..remaining {
+                    self.de.skip_payload(tag)?;
+                }
+                self.de.leave();
+            }
+            Some(Frame::Array { tag, remaining }) => {
+                let size = match tag {
+                    TAG_BYTE_ARRAY => 1,
+                    TAG_INT_ARRAY => 4,
+                    _ => 8,
+                };
+                self.de.skip_bytes(remaining * size)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the header of the next named value, or the `TAG_End` closing the compound.
+    ///
+    /// A root value of `TAG_End` has no name and no payload, so there's nothing to return.
+    fn entry<'a>(&'a mut self) -> Result<Option<Event<'a>>>
+    where
+        'de: 'a,
+    {
+        let tag = self.de.read_tag()?;
+        if tag == TAG_END {
+            if self.stack.pop().is_none() {
+                return Ok(None);
+            }
+            self.de.leave();
+            return Ok(Some(Event::EndCompound));
+        }
+        let found = to_tag(tag)?;
+
+        if tag == TAG_COMPOUND {
+            self.de.enter()?;
+            self.stack.push(Frame::Compound);
+            Ok(Some(Event::StartCompound(Some(text(self.de.read_str()?)))))
+        } else {
+            self.pending = Some(tag);
+            Ok(Some(Event::Field(found, text(self.de.read_str()?))))
+        }
+    }
+
+    /// Reads the payload of a scalar, or the start of a compound, list or array.
+    fn payload<'a>(&'a mut self, tag: u8) -> Result<Event<'a>>
+    where
+        'de: 'a,
+    {
+        let de = &mut self.de;
+        Ok(match tag {
+            TAG_BYTE => {
+                de.reserve(1)?;
+                Event::Byte(de.reader.read_i8()?)
+            }
+            TAG_SHORT => {
+                de.reserve(2)?;
+                Event::Short(de.reader.read_i16::<BigEndian>()?)
+            }
+            TAG_INT => {
+                de.reserve(4)?;
+                Event::Int(de.reader.read_i32::<BigEndian>()?)
+            }
+            TAG_LONG => {
+                de.reserve(8)?;
+                Event::Long(de.reader.read_i64::<BigEndian>()?)
+            }
+            TAG_FLOAT => {
+                de.reserve(4)?;
+                Event::Float(de.reader.read_f32::<BigEndian>()?)
+            }
+            TAG_DOUBLE => {
+                de.reserve(8)?;
+                Event::Double(de.reader.read_f64::<BigEndian>()?)
+            }
+            TAG_STRING => Event::String(text(de.read_str()?)),
+            TAG_LIST => {
+                de.enter()?;
+                de.reserve(1)?;
+                let elem = de.reader.read_u8()?;
+                let found = to_tag(elem)?;
+                let len = de.read_len()?;
+                // a list of TAG_End can't hold anything, whatever its length says
+                let remaining = if elem == TAG_END { 0 } else { len };
+                self.stack.push(Frame::List {
+                    tag: elem,
+                    remaining,
+                });
+                Event::StartList(found, remaining as usize)
+            }
+            TAG_COMPOUND => {
+                de.enter()?;
+                self.stack.push(Frame::Compound);
+                Event::StartCompound(None)
+            }
+            TAG_BYTE_ARRAY | TAG_INT_ARRAY | TAG_LONG_ARRAY => {
+                let len = de.read_len()?;
+                self.stack.push(Frame::Array {
+                    tag,
+                    remaining: len,
+                });
+                Event::StartArray(to_tag(tag)?, len as usize)
+            }
+            other => return Err(ErrorKind::UnknownTag(other).into()),
+        })
+    }
+
+    /// Reads the next `len` elements of an array.
+    fn chunk<'a>(&'a mut self, tag: u8, len: u64) -> Result<Event<'a>>
+    where
+        'de: 'a,
+    {
+        let de = &mut self.de;
+        Ok(match tag {
+            TAG_BYTE_ARRAY => Event::ByteChunk(match de.read_bytes(len)? {
+                Reference::Borrowed(b) => b,
+                Reference::Copied(c) => c,
+            }),
+            TAG_INT_ARRAY => {
+                de.reserve(len * 4)?;
+                self.ints.resize(len as usize, 0);
+                de.reader.read_i32_into::<BigEndian>(&mut self.ints)?;
+                Event::IntChunk(&self.ints)
+            }
+            _ => {
+                de.reserve(len * 8)?;
+                self.longs.resize(len as usize, 0);
+                de.reader.read_i64_into::<BigEndian>(&mut self.longs)?;
+                Event::LongChunk(&self.longs)
+            }
+        })
+    }
+}
+
+fn to_tag(id: u8) -> Result<Tag> {
+    Tag::try_from(id).map_err(|id| ErrorKind::UnknownTag(id).into())
+}
+
+fn text<'a, 'de: 'a>(s: Reference<'de, 'a, str>) -> &'a str {
+    match s {
+        Reference::Borrowed(b) => b,
+        Reference::Copied(c) => c,
+    }
+}
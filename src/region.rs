@@ -0,0 +1,630 @@
+//! Anvil region files (`.mca`), each holding the chunks of a 32 by 32 chunk area.
+//!
+//! A region file starts with two 4 KiB tables: where each chunk is stored, as an offset and a
+//! length in 4 KiB sectors, and when it was last saved. The chunks follow in whole sectors, each
+//! one a big-endian length, a compression type and the compressed NBT.
+//!
+//! Chunks too large for the 1 MiB a region file can give them are stored in a `c.X.Z.mcc` file
+//! next to the region instead, named after the chunk's absolute coordinates. Their compression
+//! type has the `0x80` bit set and nothing else is stored in the region.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    de::from_reader,
+    error::{Error, ErrorKind, Result},
+    lz4,
+    ser::Serializer,
+};
+
+pub(crate) const SECTOR_LEN: u64 = 4096;
+pub(crate) const CHUNKS: usize = 1024;
+/// The most sectors a single chunk can occupy, as their count is stored in a byte.
+const MAX_SECTORS: usize = 255;
+/// Set on the compression type of chunks stored in a `.mcc` file.
+const EXTERNAL: u8 = 0x80;
+/// The compression type of chunks compressed with a custom algorithm, whose name follows.
+const CUSTOM: u8 = 127;
+
+/// How the NBT of a chunk is compressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Compression {
+    Gzip,
+    Zlib,
+    None,
+    /// LZ4 in the block stream format of lz4-java, available since 1.20.5.
+    Lz4,
+}
+
+/// A chunk as it is stored in a region file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawChunk {
+    pub compression: Compression,
+    /// The compressed NBT.
+    pub data: Vec<u8>,
+}
+
+impl RawChunk {
+    /// Compresses the NBT in `nbt`.
+    pub fn compress(compression: Compression, nbt: &[u8]) -> Result<Self> {
+        let level = flate2::Compression::default();
+        let data = match compression {
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), level);
+                encoder.write_all(nbt)?;
+                encoder.finish()?
+            }
+            Compression::Zlib => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), level);
+                encoder.write_all(nbt)?;
+                encoder.finish()?
+            }
+            Compression::None => nbt.to_vec(),
+            Compression::Lz4 => lz4::compress(nbt),
+        };
+
+        Ok(RawChunk { compression, data })
+    }
+
+    /// Returns the uncompressed NBT.
+    pub fn decompress(&self) -> Result<Vec<u8>> {
+        let mut nbt = Vec::new();
+        self.decoder()?.read_to_end(&mut nbt)?;
+
+        Ok(nbt)
+    }
+
+    /// A reader of the uncompressed NBT.
+    fn decoder(&self) -> Result<Box<dyn Read + '_>> {
+        let data = self.data.as_slice();
+        Ok(match self.compression {
+            Compression::Gzip => Box::new(GzDecoder::new(data)),
+            Compression::Zlib => Box::new(ZlibDecoder::new(data)),
+            Compression::None => Box::new(data),
+            Compression::Lz4 => Box::new(Cursor::new(lz4::decompress(data)?)),
+        })
+    }
+}
+
+/// The index of a chunk in the header tables.
+///
+/// Coordinates are taken modulo 32, so both coordinates within the region and absolute chunk
+/// coordinates can be used.
+pub(crate) fn index(x: i32, z: i32) -> usize {
+    (x.rem_euclid(32) + z.rem_euclid(32) * 32) as usize
+}
+
+/// The coordinates within the region of the chunk at `index`.
+pub(crate) fn position(index: usize) -> (i32, i32) {
+    ((index % 32) as i32, (index / 32) as i32)
+}
+
+/// Reads chunks from a region file.
+///
+/// Chunks are addressed by their x and z coordinate, either within the region (`0..32`) or
+/// absolute, since only the coordinates modulo 32 are used.
+pub struct RegionReader<R> {
+    reader: R,
+    /// Offset and length in sectors of each chunk, `0` for chunks that aren't present.
+    locations: Box<[u32; CHUNKS]>,
+    /// When each chunk was last saved, in seconds since the Unix epoch.
+    timestamps: Box<[u32; CHUNKS]>,
+    external: Option<External>,
+}
+
+/// Where the `.mcc` files of a region are.
+struct External {
+    dir: PathBuf,
+    /// The coordinates of the region, to name files after absolute chunk coordinates.
+    x: i32,
+    z: i32,
+}
+
+impl External {
+    fn from_path(path: &Path) -> Option<Self> {
+        let (x, z) = region_coords(path)?;
+
+        Some(External {
+            dir: path.parent()?.to_path_buf(),
+            x,
+            z,
+        })
+    }
+}
+
+/// The region coordinates in a file name like `r.-1.2.mca`.
+pub(crate) fn region_coords(path: &Path) -> Option<(i32, i32)> {
+    let name = path.file_name()?.to_str()?;
+    let mut parts = name.strip_prefix("r.")?.strip_suffix(".mca")?.split('.');
+    let x = parts.next()?.parse().ok()?;
+    let z = parts.next()?.parse().ok()?;
+
+    parts.next().is_none().then_some((x, z))
+}
+
+impl RegionReader<BufReader<File>> {
+    /// Opens the region file at `path`. Chunks stored in `.mcc` files are looked up next to it
+    /// if the file is named like `r.X.Z.mca`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let mut region = RegionReader::new(BufReader::new(File::open(path)?))?;
+        region.external = External::from_path(path);
+
+        Ok(region)
+    }
+}
+
+impl<R: Read + Seek> RegionReader<R> {
+    /// Reads the header tables from `reader`. An empty file is read as a region without chunks.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut locations = Box::new([0; CHUNKS]);
+        let mut timestamps = Box::new([0; CHUNKS]);
+
+        if reader.seek(SeekFrom::End(0))? > 0 {
+            reader.seek(SeekFrom::Start(0))?;
+            reader.read_u32_into::<BigEndian>(&mut locations[..])?;
+            reader.read_u32_into::<BigEndian>(&mut timestamps[..])?;
+        }
+
+        Ok(Self {
+            reader,
+            locations,
+            timestamps,
+            external: None,
+        })
+    }
+
+    /// Sets where `.mcc` files of chunks too large for the region are, and the coordinates of the
+    /// region they are named after. [`RegionReader::open`] does this for regular file names.
+    pub fn with_external_dir(mut self, dir: impl Into<PathBuf>, x: i32, z: i32) -> Self {
+        self.external = Some(External {
+            dir: dir.into(),
+            x,
+            z,
+        });
+        self
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    pub fn contains(&self, x: i32, z: i32) -> bool {
+        self.locations[index(x, z)] != 0
+    }
+
+    /// When the chunk was last saved, in seconds since the Unix epoch.
+    pub fn timestamp(&self, x: i32, z: i32) -> Option<u32> {
+        let index = index(x, z);
+        (self.locations[index] != 0).then_some(self.timestamps[index])
+    }
+
+    /// The coordinates within the region of every chunk that is present.
+    pub fn positions(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        (0..CHUNKS)
+            .filter(|&index| self.locations[index] != 0)
+            .map(position)
+    }
+
+    /// Reads the chunk without decompressing it, from the region or its `.mcc` file.
+    ///
+    /// Chunks compressed with a custom algorithm fail with
+    /// [`ErrorKind::UnsupportedCompression`] naming the algorithm.
+    pub fn raw_chunk(&mut self, x: i32, z: i32) -> Result<Option<RawChunk>> {
+        let index = index(x, z);
+        let location = self.locations[index];
+        if location == 0 {
+            return Ok(None);
+        }
+
+        let (start, sectors) = sectors(location);
+        let start = start as u64 * SECTOR_LEN;
+        self.raw_chunk_at(index, start, sectors as u64)
+            .map(Some)
+            .map_err(|e| e.with_offset(start))
+    }
+
+    fn raw_chunk_at(&mut self, index: usize, start: u64, sectors: u64) -> Result<RawChunk> {
+        self.reader.seek(SeekFrom::Start(start))?;
+        let len = self.reader.read_u32::<BigEndian>()?;
+        if len == 0 || u64::from(len) + 4 > sectors * SECTOR_LEN {
+            return Err(ErrorKind::InvalidChunkLength(len).into());
+        }
+        let id = self.reader.read_u8()?;
+
+        let data = if id & EXTERNAL != 0 {
+            fs::read(self.external_path(index)?)?
+        } else {
+            let mut data = Vec::new();
+            (&mut self.reader)
+                .take(u64::from(len) - 1)
+                .read_to_end(&mut data)?;
+            if data.len() as u64 != u64::from(len) - 1 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            data
+        };
+
+        let compression = match id & !EXTERNAL {
+            CUSTOM => return Err(custom_compression(&data)),
+            id => Compression::try_from(id)?,
+        };
+
+        Ok(RawChunk { compression, data })
+    }
+
+    /// Whether the chunk at `index` is stored in a `.mcc` file.
+    fn is_external(&mut self, index: usize) -> Result<bool> {
+        let location = self.locations[index];
+        if location == 0 {
+            return Ok(false);
+        }
+
+        let (start, _) = sectors(location);
+        self.reader
+            .seek(SeekFrom::Start(start as u64 * SECTOR_LEN + 4))?;
+        Ok(self.reader.read_u8()? & EXTERNAL != 0)
+    }
+
+    fn external_path(&self, index: usize) -> Result<PathBuf> {
+        let external = self.external.as_ref().ok_or(ErrorKind::UnknownRegionPath)?;
+        let (x, z) = position(index);
+        let name = format!("c.{}.{}.mcc", external.x * 32 + x, external.z * 32 + z);
+
+        Ok(external.dir.join(name))
+    }
+
+    /// Reads the chunk's uncompressed NBT, e.g. for an [`NbtReader`](crate::NbtReader).
+    pub fn chunk_bytes(&mut self, x: i32, z: i32) -> Result<Option<Vec<u8>>> {
+        self.raw_chunk(x, z)?
+            .map(|chunk| chunk.decompress())
+            .transpose()
+    }
+
+    /// Reads and deserializes the chunk.
+    pub fn chunk<T: DeserializeOwned>(&mut self, x: i32, z: i32) -> Result<Option<T>> {
+        self.raw_chunk(x, z)?
+            .map(|chunk| from_reader(chunk.decoder()?))
+            .transpose()
+    }
+
+    /// Iterates over all chunks that are present, together with their coordinates within the
+    /// region.
+    pub fn chunks<T: DeserializeOwned>(&mut self) -> Chunks<'_, R, T> {
+        Chunks {
+            region: self,
+            index: 0,
+            marker: PhantomData,
+        }
+    }
+}
+
+/// The chunks of a region, see [`RegionReader::chunks`].
+pub struct Chunks<'a, R, T> {
+    region: &'a mut RegionReader<R>,
+    index: usize,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<R: Read + Seek, T: DeserializeOwned> Iterator for Chunks<'_, R, T> {
+    type Item = Result<(i32, i32, T)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.index < CHUNKS {
+            let index = self.index;
+            self.index += 1;
+
+            let (x, z) = position(index);
+            match self.region.chunk(x, z) {
+                Ok(Some(chunk)) => return Some(Ok((x, z, chunk))),
+                Ok(None) => {}
+                Err(e) => return Some(Err(e)),
+            }
+        }
+
+        None
+    }
+}
+
+/// Writes chunks into a region file, in place.
+///
+/// A rewritten chunk reuses its sectors if it still fits, otherwise it moves to the first gap
+/// large enough, e.g. one left by a removed chunk, or to the end of the file. Gaps that remain can
+/// be squeezed out with [`RegionWriter::compact`].
+///
+/// Chunks larger than 1 MiB after compression are written to a `.mcc` file next to the region,
+/// which requires the region to be opened with [`RegionWriter::open`] or given a directory with
+/// [`RegionWriter::with_external_dir`].
+pub struct RegionWriter<F> {
+    region: RegionReader<F>,
+    compression: Compression,
+    /// Which sectors are occupied by the header or a chunk.
+    used: Vec<bool>,
+    /// `File::set_len` for files opened with `open`, to cut off what `compact` freed.
+    set_len: Option<fn(&mut F, u64) -> io::Result<()>>,
+}
+
+impl RegionWriter<File> {
+    /// Opens the region file at `path` for reading and writing, creating it if it doesn't exist.
+    /// `.mcc` files are kept next to it if the file is named like `r.X.Z.mca`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let mut writer = RegionWriter::new(file)?;
+        writer.set_len = Some(|file, len| file.set_len(len));
+        writer.region.external = External::from_path(path);
+
+        Ok(writer)
+    }
+}
+
+impl<F: Read + Write + Seek> RegionWriter<F> {
+    /// Reads the header tables from `inner`, or writes empty ones if it's empty.
+    pub fn new(mut inner: F) -> Result<Self> {
+        if inner.seek(SeekFrom::End(0))? == 0 {
+            inner.write_all(&[0; 2 * SECTOR_LEN as usize])?;
+        }
+        let region = RegionReader::new(inner)?;
+
+        let mut used = vec![true; 2];
+        for &location in region.locations.iter().filter(|&&l| l != 0) {
+            let (start, sectors) = sectors(location);
+            if used.len() < start + sectors {
+                used.resize(start + sectors, false);
+            }
+            used[start..start + sectors].fill(true);
+        }
+
+        Ok(Self {
+            region,
+            compression: Compression::Zlib,
+            used,
+            set_len: None,
+        })
+    }
+
+    /// Sets how chunks written by [`write_chunk`](RegionWriter::write_chunk) are compressed,
+    /// zlib by default like the game does.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets where `.mcc` files are kept, see [`RegionReader::with_external_dir`].
+    pub fn with_external_dir(mut self, dir: impl Into<PathBuf>, x: i32, z: i32) -> Self {
+        self.region = self.region.with_external_dir(dir, x, z);
+        self
+    }
+
+    /// The region for reading chunks back.
+    pub fn reader(&mut self) -> &mut RegionReader<F> {
+        &mut self.region
+    }
+
+    pub fn into_inner(self) -> F {
+        self.region.reader
+    }
+
+    /// Serializes `value` as the chunk at `x`, `z`, replacing the chunk that was there.
+    pub fn write_chunk<T: ?Sized + Serialize>(&mut self, x: i32, z: i32, value: &T) -> Result<()> {
+        let mut nbt = Vec::new();
+        Serializer::new(&mut nbt).serialize_named("", value)?;
+
+        self.write_raw_chunk(x, z, &RawChunk::compress(self.compression, &nbt)?)
+    }
+
+    /// Writes an already compressed chunk, stamped with the current time.
+    pub fn write_raw_chunk(&mut self, x: i32, z: i32, chunk: &RawChunk) -> Result<()> {
+        let index = index(x, z);
+        let was_external = self.region.is_external(index)?;
+
+        let mut id = u8::from(chunk.compression);
+        let mut data = chunk.data.as_slice();
+        // the length counts the compression byte as well
+        let external = (data.len() + 5).div_ceil(SECTOR_LEN as usize) > MAX_SECTORS;
+        if external {
+            let path = self
+                .region
+                .external_path(index)
+                .map_err(|_| Error::from(ErrorKind::ChunkTooLarge(data.len())))?;
+            fs::write(path, data)?;
+            id |= EXTERNAL;
+            data = &[];
+        }
+        let len = data.len() + 1;
+        let needed = (len + 4).div_ceil(SECTOR_LEN as usize);
+
+        self.free(index);
+        let start = self.allocate(needed);
+
+        let writer = &mut self.region.reader;
+        writer.seek(SeekFrom::Start(start as u64 * SECTOR_LEN))?;
+        writer.write_u32::<BigEndian>(u32::try_from(len)?)?;
+        writer.write_u8(id)?;
+        writer.write_all(data)?;
+        let padding = needed * SECTOR_LEN as usize - len - 4;
+        writer.write_all(&vec![0; padding])?;
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs() as u32);
+        self.set_entry(index, (start as u32) << 8 | needed as u32, timestamp)?;
+
+        if was_external && !external {
+            self.remove_external(index)?;
+        }
+        Ok(())
+    }
+
+    /// Removes the chunk at `x`, `z` and returns whether there was one. Its sectors are reused
+    /// by later writes.
+    pub fn remove_chunk(&mut self, x: i32, z: i32) -> Result<bool> {
+        let index = index(x, z);
+        if self.region.locations[index] == 0 {
+            return Ok(false);
+        }
+
+        let was_external = self.region.is_external(index)?;
+        self.free(index);
+        self.set_entry(index, 0, 0)?;
+        if was_external {
+            self.remove_external(index)?;
+        }
+
+        Ok(true)
+    }
+
+    /// Moves all chunks to the front of the file, in the order they are stored, so no sectors
+    /// are left unused. Chunks also give up sectors they don't need.
+    ///
+    /// Returns the new length of the region in bytes. Files opened with
+    /// [`open`](RegionWriter::open) are truncated to it, other storage has to be cut off by the
+    /// caller.
+    pub fn compact(&mut self) -> Result<u64> {
+        let mut chunks: Vec<_> = (0..CHUNKS)
+            .filter(|&index| self.region.locations[index] != 0)
+            .map(|index| (sectors(self.region.locations[index]), index))
+            .collect();
+        chunks.sort_unstable();
+
+        let mut next = 2;
+        for ((start, sectors), index) in chunks {
+            let reader = &mut self.region.reader;
+            reader.seek(SeekFrom::Start(start as u64 * SECTOR_LEN))?;
+            let len = reader.read_u32::<BigEndian>()? as usize;
+            let needed = (len + 4).div_ceil(SECTOR_LEN as usize);
+            if len == 0 || needed > sectors {
+                return Err(ErrorKind::InvalidChunkLength(len as u32).into());
+            }
+
+            if start != next || needed != sectors {
+                // chunks only ever move towards the front, so reading it whole before writing
+                // can't overwrite anything that is still needed
+                let mut data = vec![0; needed * SECTOR_LEN as usize];
+                reader.seek(SeekFrom::Start(start as u64 * SECTOR_LEN))?;
+                reader.read_exact(&mut data[..len + 4])?;
+
+                reader.seek(SeekFrom::Start(next as u64 * SECTOR_LEN))?;
+                reader.write_all(&data)?;
+                let timestamp = self.region.timestamps[index];
+                self.set_entry(index, (next as u32) << 8 | needed as u32, timestamp)?;
+            }
+            next += needed;
+        }
+
+        self.used = vec![true; next];
+        let len = next as u64 * SECTOR_LEN;
+        if let Some(set_len) = self.set_len {
+            set_len(&mut self.region.reader, len)?;
+        }
+
+        Ok(len)
+    }
+
+    fn remove_external(&mut self, index: usize) -> Result<()> {
+        match fs::remove_file(self.region.external_path(index)?) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Marks the sectors of the chunk at `index` as unused.
+    fn free(&mut self, index: usize) {
+        let location = self.region.locations[index];
+        if location != 0 {
+            let (start, sectors) = sectors(location);
+            self.used[start..start + sectors].fill(false);
+        }
+    }
+
+    /// Finds the first `len` consecutive unused sectors and marks them as used.
+    fn allocate(&mut self, len: usize) -> usize {
+        let mut start = 0;
+        for (i, &used) in self.used.iter().enumerate() {
+            if used {
+                start = i + 1;
+            } else if i + 1 - start == len {
+                break;
+            }
+        }
+
+        if self.used.len() < start + len {
+            self.used.resize(start + len, false);
+        }
+        self.used[start..start + len].fill(true);
+
+        start
+    }
+
+    fn set_entry(&mut self, index: usize, location: u32, timestamp: u32) -> Result<()> {
+        self.region.locations[index] = location;
+        self.region.timestamps[index] = timestamp;
+
+        let writer = &mut self.region.reader;
+        writer.seek(SeekFrom::Start(index as u64 * 4))?;
+        writer.write_u32::<BigEndian>(location)?;
+        writer.seek(SeekFrom::Start(SECTOR_LEN + index as u64 * 4))?;
+        writer.write_u32::<BigEndian>(timestamp)?;
+
+        Ok(())
+    }
+}
+
+/// The first sector and number of sectors of a chunk's location.
+fn sectors(location: u32) -> (usize, usize) {
+    ((location >> 8) as usize, (location & 0xff) as usize)
+}
+
+/// The error for a chunk compressed with a custom algorithm, whose name is stored in front of
+/// the data.
+fn custom_compression(data: &[u8]) -> Error {
+    let name = data
+        .get(..2)
+        .map(|len| usize::from(u16::from_be_bytes([len[0], len[1]])))
+        .and_then(|len| data.get(2..2 + len))
+        .map(String::from_utf8_lossy)
+        .unwrap_or_default();
+
+    ErrorKind::UnsupportedCompression(name.into_owned()).into()
+}
+
+impl From<Compression> for u8 {
+    /// The id stored in front of the chunk.
+    fn from(compression: Compression) -> u8 {
+        match compression {
+            Compression::Gzip => 1,
+            Compression::Zlib => 2,
+            Compression::None => 3,
+            Compression::Lz4 => 4,
+        }
+    }
+}
+
+impl TryFrom<u8> for Compression {
+    type Error = Error;
+
+    fn try_from(id: u8) -> Result<Self> {
+        match id {
+            1 => Ok(Compression::Gzip),
+            2 => Ok(Compression::Zlib),
+            3 => Ok(Compression::None),
+            4 => Ok(Compression::Lz4),
+            id => Err(ErrorKind::UnknownCompression(id).into()),
+        }
+    }
+}
@@ -1,180 +1,554 @@
-use crate::error::{Error, Result};
+use crate::error::{Error, ErrorKind, Result};
 use crate::nbt::*;
-use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-use rnbt::{write_nbt, NbtField, NbtList, NbtValue};
+use crate::options::UnsignedPolicy;
+use byteorder::{BigEndian, WriteBytesExt};
+use flate2::write::GzEncoder;
 use serde::{ser, Serialize};
-use std::io::{Read, Write};
+use std::io::{self, Cursor, Seek, SeekFrom, Write};
 
 // ---- public methods -----------------------------------------------------------------------------
-pub fn to_writer<W: std::io::Write, T: Serialize>(
-    writer: &mut W,
+pub fn to_writer<W: Write, T: ?Sized + Serialize>(writer: W, value: &T, name: &str) -> Result<()> {
+    let mut serializer = Serializer::new(writer);
+
+    serializer.serialize_named(name, value)
+}
+
+/// Like [`to_writer`], but gzips the output, as the game does for `level.dat` and player files.
+pub fn to_gzip_writer<W: Write, T: ?Sized + Serialize>(
+    writer: W,
     value: &T,
-    name: String,
+    name: &str,
 ) -> Result<()> {
-    let mut serializer = Serializer::new(name, writer);
-
-    value.serialize(&mut serializer)?;
+    let mut encoder = GzEncoder::new(writer, flate2::Compression::default());
+    to_writer(&mut encoder, value, name)?;
+    encoder.finish()?;
 
     Ok(())
 }
 
 // ---- Serializer struct --------------------------------------------------------------------------
-pub struct Serializer<'a, W: std::io::Write> {
-    name: String,
-    writer: &'a mut W,
+/// Writes values as NBT.
+///
+/// Serializing into `&mut Serializer` writes a root value with an empty name, use
+/// [`Serializer::serialize_named`] to name it. Either can be called repeatedly to write several
+/// root values to the same writer.
+///
+/// Sequences of unknown length, e.g. from [`collect_seq`](ser::Serializer::collect_seq) on a
+/// filtered iterator, are buffered in memory until their length is known. Writers implementing
+/// [`Seek`] can opt into writing them in place with [`Serializer::with_seek`].
+///
+/// By default compound entries are written in the order they are serialized, which for a
+/// `HashMap` changes from run to run. [`Serializer::with_canonical`] makes the output depend
+/// only on the value being written.
+pub struct Serializer<W> {
+    pub(crate) writer: W,
+    unsigned: UnsignedPolicy,
+    canonical: bool,
+    /// `Seek::seek` of the writer, if enabled by `with_seek`.
+    seek: Option<fn(&mut W, SeekFrom) -> io::Result<u64>>,
+}
+
+impl<W: Write> Serializer<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            unsigned: UnsignedPolicy::default(),
+            canonical: false,
+            seek: None,
+        }
+    }
+
+    /// Sets how unsigned integers are mapped onto NBT tags, see [`UnsignedPolicy`].
+    pub fn with_unsigned_policy(mut self, policy: UnsignedPolicy) -> Self {
+        self.unsigned = policy;
+        self
+    }
+
+    /// Writes compound entries sorted by key, struct fields and map entries alike, and
+    /// normalizes floats: `-0.0` is written as `0.0` and every NaN as the same quiet NaN.
+    ///
+    /// Equal values are then written as identical bytes, at the cost of buffering each compound
+    /// in memory until all of its entries are known.
+    pub fn with_canonical(mut self) -> Self {
+        self.canonical = true;
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Writes the count of sequences of unknown length as a placeholder and seeks back to patch
+    /// it once the sequence is done, instead of buffering the sequence.
+    pub fn with_seek(mut self) -> Self
+    where
+        W: Seek,
+    {
+        self.seek = Some(W::seek);
+        self
+    }
+
+    /// Writes `value` as a root value called `name`.
+    pub fn serialize_named<T: ?Sized + Serialize>(&mut self, name: &str, value: &T) -> Result<()> {
+        value.serialize(ValueSerializer {
+            ser: self,
+            header: Header::Named(name),
+        })
+    }
+
+    fn root(&mut self) -> ValueSerializer<'_, 'static, W> {
+        ValueSerializer {
+            ser: self,
+            header: Header::Named(""),
+        }
+    }
+
+    /// An in-memory serializer with the same options.
+    fn buffer(&self) -> Serializer<Cursor<Vec<u8>>> {
+        Serializer {
+            writer: Cursor::new(Vec::new()),
+            unsigned: self.unsigned,
+            canonical: self.canonical,
+            seek: Some(<Cursor<Vec<u8>> as Seek>::seek),
+        }
+    }
+}
+
+/// What has to be written in front of a value's payload.
+pub(crate) enum Header<'n> {
+    /// Tag and name, for root values and compound entries.
+    Named(&'n str),
+    /// Nothing for list elements, except for the first one which writes the element tag and
+    /// length of the list.
+    Element(&'n mut ListState),
+    /// The length of an int or long array, whose elements are written with this tag but
+    /// without headers. Only sequences can be written this way.
+    Array(u8),
 }
 
-impl<'a, W: Write> Serializer<'a, W> {
-    pub fn new(name: String, writer: &'a mut W) -> Self {
-        Self { name, writer }
+pub(crate) struct ListState {
+    /// Number of elements, `None` if unknown until the list is done.
+    pub(crate) len: Option<usize>,
+    /// Tag of the elements, known once the first one has been written.
+    pub(crate) tag: Option<u8>,
+    pub(crate) written: usize,
+}
+
+/// Serializes a single value, preceded by the header its position requires.
+pub(crate) struct ValueSerializer<'a, 'n, W> {
+    pub(crate) ser: &'a mut Serializer<W>,
+    pub(crate) header: Header<'n>,
+}
+
+impl<W: Write> ValueSerializer<'_, '_, W> {
+    pub(crate) fn write_header(&mut self, tag: u8) -> Result<()> {
+        let writer = &mut self.ser.writer;
+        match &mut self.header {
+            Header::Named(name) => {
+                trace_event!(name = *name, tag = crate::nbt::tag_name(tag), "write");
+
+                writer.write_u8(tag)?;
+                write_name(writer, name)?;
+            }
+            Header::Element(list) => match list.tag {
+                None => {
+                    writer.write_u8(tag)?;
+                    let len = list.len.unwrap_or_default();
+                    writer.write_i32::<BigEndian>(i32::try_from(len)?)?;
+                    list.tag = Some(tag);
+                }
+                Some(expected) if expected != tag => {
+                    return Err(Error::incompatible_list_type(expected, tag));
+                }
+                Some(_) => {}
+            },
+            Header::Array(_) => return Err(Error::unexpected_tag(TAG_LIST, tag)),
+        }
+
+        Ok(())
     }
 }
 
 // ---- Impls --------------------------------------------------------------------------------------
-impl<'a, W: Write> ser::Serializer for &'a mut Serializer<'a, W> {
+impl<'a, W: Write> ser::Serializer for ValueSerializer<'a, '_, W> {
     type Ok = ();
 
     type Error = crate::error::Error;
 
-    type SerializeSeq = Self;
+    type SerializeSeq = List<'a, W>;
 
-    type SerializeTuple = Self;
+    type SerializeTuple = List<'a, W>;
 
-    type SerializeTupleStruct = Self;
+    type SerializeTupleStruct = List<'a, W>;
 
-    type SerializeTupleVariant = Self;
+    type SerializeTupleVariant = ser::Impossible<(), Error>;
 
-    type SerializeMap = Self;
+    type SerializeMap = Compound<'a, W>;
 
-    type SerializeStruct = Self;
+    type SerializeStruct = Compound<'a, W>;
 
-    type SerializeStructVariant = Self;
+    type SerializeStructVariant = ser::Impossible<(), Error>;
 
-    fn serialize_bool(self, v: bool) -> Result<Self::Ok> {
-        self.writer.write_u8(TAG_BYTE)?;
-        write_name(&mut self.writer, &self.name)?;
-        self.writer.write_u8(if v { 1 } else { 0 })?;
+    fn serialize_bool(mut self, v: bool) -> Result<Self::Ok> {
+        self.write_header(TAG_BYTE)?;
+        self.ser.writer.write_u8(if v { 1 } else { 0 })?;
 
         Ok(())
     }
 
-    fn serialize_i8(self, v: i8) -> Result<Self::Ok> {
-        self.writer.write_u8(TAG_SHORT)?;
-        write_name(&mut self.writer, &self.name)?;
-        self.writer.write_i16::<BigEndian>(v as i16)?;
+    fn serialize_i8(mut self, v: i8) -> Result<Self::Ok> {
+        self.write_header(TAG_BYTE)?;
+        self.ser.writer.write_i8(v)?;
 
         Ok(())
     }
 
-    fn serialize_i16(self, v: i16) -> Result<Self::Ok> {
-        self.writer.write_u8(TAG_SHORT)?;
-        write_name(&mut self.writer, &self.name)?;
-        self.writer.write_i16::<BigEndian>(v)?;
+    fn serialize_i16(mut self, v: i16) -> Result<Self::Ok> {
+        self.write_header(TAG_SHORT)?;
+        self.ser.writer.write_i16::<BigEndian>(v)?;
 
         Ok(())
     }
 
-    fn serialize_i32(self, v: i32) -> Result<Self::Ok> {
-        self.writer.write_u8(TAG_INT)?;
-        write_name(&mut self.writer, &self.name)?;
-        self.writer.write_i32::<BigEndian>(v)?;
+    fn serialize_i32(mut self, v: i32) -> Result<Self::Ok> {
+        self.write_header(TAG_INT)?;
+        self.ser.writer.write_i32::<BigEndian>(v)?;
 
         Ok(())
     }
 
-    fn serialize_i64(self, v: i64) -> Result<Self::Ok> {
-        self.writer.write_u8(TAG_LONG)?;
-        write_name(&mut self.writer, &self.name)?;
-        self.writer.write_i64::<BigEndian>(v)?;
+    fn serialize_i64(mut self, v: i64) -> Result<Self::Ok> {
+        self.write_header(TAG_LONG)?;
+        self.ser.writer.write_i64::<BigEndian>(v)?;
 
         Ok(())
     }
 
     fn serialize_u8(self, v: u8) -> Result<Self::Ok> {
-        self.writer.write_u8(TAG_BYTE)?;
-        write_name(&mut self.writer, &self.name)?;
-        self.writer.write_u8(v)?;
-
-        Ok(())
+        match self.ser.unsigned {
+            // always a byte, see `UnsignedPolicy::Widen`
+            UnsignedPolicy::Widen | UnsignedPolicy::Reinterpret => self.serialize_i8(v as i8),
+            UnsignedPolicy::Reject => Err(ErrorKind::UnsignedRejected.into()),
+        }
     }
 
     fn serialize_u16(self, v: u16) -> Result<Self::Ok> {
-        self.writer.write_u8(TAG_INT)?;
-        write_name(&mut self.writer, &self.name)?;
-        self.writer.write_i32::<BigEndian>(v as i32)?;
-
-        Ok(())
+        match self.ser.unsigned {
+            UnsignedPolicy::Widen => self.serialize_i32(v as i32),
+            UnsignedPolicy::Reinterpret => self.serialize_i16(v as i16),
+            UnsignedPolicy::Reject => Err(ErrorKind::UnsignedRejected.into()),
+        }
     }
 
     fn serialize_u32(self, v: u32) -> Result<Self::Ok> {
-        self.writer.write_u8(TAG_LONG)?;
-        write_name(&mut self.writer, &self.name)?;
-        self.writer.write_i64::<BigEndian>(v as i64)?;
+        match self.ser.unsigned {
+            UnsignedPolicy::Widen => self.serialize_i64(v as i64),
+            UnsignedPolicy::Reinterpret => self.serialize_i32(v as i32),
+            UnsignedPolicy::Reject => Err(ErrorKind::UnsignedRejected.into()),
+        }
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok> {
+        match self.ser.unsigned {
+            UnsignedPolicy::Widen => self.serialize_i64(i64::try_from(v)?),
+            UnsignedPolicy::Reinterpret => self.serialize_i64(v as i64),
+            UnsignedPolicy::Reject => Err(ErrorKind::UnsignedRejected.into()),
+        }
+    }
+
+    fn serialize_f32(mut self, mut v: f32) -> Result<Self::Ok> {
+        if self.ser.canonical {
+            if v.is_nan() {
+                v = f32::NAN;
+            } else if v == 0.0 {
+                // also true for -0.0
+                v = 0.0;
+            }
+        }
+        self.write_header(TAG_FLOAT)?;
+        self.ser.writer.write_f32::<BigEndian>(v)?;
 
         Ok(())
     }
 
-    fn serialize_u64(self, v: u64) -> Result<Self::Ok> {
-        self.writer.write_u8(TAG_LONG)?;
-        write_name(&mut self.writer, &self.name)?;
-        self.writer.write_i64::<BigEndian>(i64::try_from(v)?)?;
+    fn serialize_f64(mut self, mut v: f64) -> Result<Self::Ok> {
+        if self.ser.canonical {
+            if v.is_nan() {
+                v = f64::NAN;
+            } else if v == 0.0 {
+                // also true for -0.0
+                v = 0.0;
+            }
+        }
+        self.write_header(TAG_DOUBLE)?;
+        self.ser.writer.write_f64::<BigEndian>(v)?;
 
         Ok(())
     }
 
-    fn serialize_f32(self, v: f32) -> Result<Self::Ok> {
-        self.writer.write_u8(TAG_FLOAT)?;
-        write_name(&mut self.writer, &self.name)?;
-        self.writer.write_f32::<BigEndian>(v)?;
+    fn serialize_char(self, v: char) -> Result<Self::Ok> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(mut self, v: &str) -> Result<Self::Ok> {
+        self.write_header(TAG_STRING)?;
+        write_name(&mut self.ser.writer, v)?;
 
         Ok(())
     }
 
-    fn serialize_f64(self, v: f64) -> Result<Self::Ok> {
-        self.writer.write_u8(TAG_DOUBLE)?;
-        write_name(&mut self.writer, &self.name)?;
-        self.writer.write_f64::<BigEndian>(v)?;
+    fn serialize_bytes(mut self, v: &[u8]) -> Result<Self::Ok> {
+        self.write_header(TAG_BYTE_ARRAY)?;
+        self.ser
+            .writer
+            .write_i32::<BigEndian>(i32::try_from(v.len())?)?;
+        self.ser.writer.write_all(v)?;
 
         Ok(())
     }
 
+    fn serialize_none(self) -> Result<Self::Ok> {
+        // a missing entry is simply not written, but every element of a list has to be there
+        match self.header {
+            Header::Named(_) => Ok(()),
+            Header::Element(_) | Header::Array(_) => Err(<Error as ser::Error>::custom(
+                "lists can't hold missing values",
+            )),
+        }
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok> {
+        todo!()
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok> {
+        todo!()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<Self::Ok> {
+        todo!()
+    }
+
+    fn serialize_newtype_struct<T>(mut self, name: &'static str, value: &T) -> Result<Self::Ok>
+    where
+        T: ?Sized + Serialize,
+    {
+        let (tag, elem) = match name {
+            INT_ARRAY_TOKEN => (TAG_INT_ARRAY, TAG_INT),
+            LONG_ARRAY_TOKEN => (TAG_LONG_ARRAY, TAG_LONG),
+            _ => return value.serialize(self),
+        };
+
+        self.write_header(tag)?;
+        value.serialize(ValueSerializer {
+            ser: self.ser,
+            header: Header::Array(elem),
+        })
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok>
+    where
+        T: ?Sized + Serialize,
+    {
+        todo!()
+    }
+
+    fn serialize_seq(mut self, len: Option<usize>) -> Result<Self::SerializeSeq> {
+        if let Header::Array(elem) = self.header {
+            let len =
+                len.ok_or_else(|| <Error as ser::Error>::custom("arrays need a known length"))?;
+            self.ser
+                .writer
+                .write_i32::<BigEndian>(i32::try_from(len)?)?;
+
+            return Ok(List {
+                ser: self.ser,
+                state: ListState {
+                    len: Some(len),
+                    tag: Some(elem),
+                    written: 0,
+                },
+                count: Count::Known,
+            });
+        }
+        self.write_header(TAG_LIST)?;
+
+        let count = match (len, self.ser.seek) {
+            (Some(_), _) => Count::Known,
+            (None, Some(seek)) => Count::Patch(seek(&mut self.ser.writer, SeekFrom::Current(0))?),
+            (None, None) => Count::Buffer(Box::new(self.ser.buffer())),
+        };
+
+        Ok(List {
+            ser: self.ser,
+            state: ListState {
+                len,
+                tag: None,
+                written: 0,
+            },
+            count,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        todo!()
+    }
+
+    fn serialize_map(mut self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        self.write_header(TAG_COMPOUND)?;
+
+        Ok(Compound::new(self.ser))
+    }
+
+    fn serialize_struct(
+        mut self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct> {
+        self.write_header(TAG_COMPOUND)?;
+
+        Ok(Compound::new(self.ser))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        todo!()
+    }
+}
+
+impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
+    type Ok = ();
+
+    type Error = crate::error::Error;
+
+    type SerializeSeq = List<'a, W>;
+
+    type SerializeTuple = List<'a, W>;
+
+    type SerializeTupleStruct = List<'a, W>;
+
+    type SerializeTupleVariant = ser::Impossible<(), Error>;
+
+    type SerializeMap = Compound<'a, W>;
+
+    type SerializeStruct = Compound<'a, W>;
+
+    type SerializeStructVariant = ser::Impossible<(), Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok> {
+        self.root().serialize_bool(v)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok> {
+        self.root().serialize_i8(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok> {
+        self.root().serialize_i16(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok> {
+        self.root().serialize_i32(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok> {
+        self.root().serialize_i64(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok> {
+        self.root().serialize_u8(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok> {
+        self.root().serialize_u16(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok> {
+        self.root().serialize_u32(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok> {
+        self.root().serialize_u64(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok> {
+        self.root().serialize_f32(v)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok> {
+        self.root().serialize_f64(v)
+    }
+
     fn serialize_char(self, v: char) -> Result<Self::Ok> {
-        self.serialize_str(&v.to_string())
+        self.root().serialize_char(v)
     }
 
     fn serialize_str(self, v: &str) -> Result<Self::Ok> {
-        self.writer.write_u8(TAG_STRING)?;
-        write_name(&mut self.writer, &self.name)?;
-        self.writer.write_i16::<BigEndian>(v.len() as i16)?;
-        self.writer.write_all(v.as_bytes())?;
-        Ok(())
+        self.root().serialize_str(v)
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok> {
-        self.writer.write_u8(TAG_BYTE_ARRAY)?;
-        write_name(&mut self.writer, &self.name)?;
-        self.writer.write_i32::<BigEndian>(v.len() as i32)?;
-        self.writer.write_all(v);
-        Ok(())
+        self.root().serialize_bytes(v)
     }
 
     fn serialize_none(self) -> Result<Self::Ok> {
-        Ok(())
+        self.root().serialize_none()
     }
 
     fn serialize_some<T>(self, value: &T) -> Result<Self::Ok>
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(self)
+        self.root().serialize_some(value)
     }
 
     fn serialize_unit(self) -> Result<Self::Ok> {
-        todo!()
+        self.root().serialize_unit()
     }
 
     fn serialize_unit_struct(self, name: &'static str) -> Result<Self::Ok> {
-        todo!()
+        self.root().serialize_unit_struct(name)
     }
 
     fn serialize_unit_variant(
@@ -183,14 +557,15 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<'a, W> {
         variant_index: u32,
         variant: &'static str,
     ) -> Result<Self::Ok> {
-        todo!()
+        self.root()
+            .serialize_unit_variant(name, variant_index, variant)
     }
 
     fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<Self::Ok>
     where
         T: ?Sized + Serialize,
     {
-        todo!()
+        self.root().serialize_newtype_struct(name, value)
     }
 
     fn serialize_newtype_variant<T>(
@@ -203,15 +578,16 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<'a, W> {
     where
         T: ?Sized + Serialize,
     {
-        todo!()
+        self.root()
+            .serialize_newtype_variant(name, variant_index, variant, value)
     }
 
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
-        Ok(self)
+        self.root().serialize_seq(len)
     }
 
     fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
-        todo!()
+        self.root().serialize_tuple(len)
     }
 
     fn serialize_tuple_struct(
@@ -219,7 +595,7 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<'a, W> {
         name: &'static str,
         len: usize,
     ) -> Result<Self::SerializeTupleStruct> {
-        todo!()
+        self.root().serialize_tuple_struct(name, len)
     }
 
     fn serialize_tuple_variant(
@@ -229,18 +605,16 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<'a, W> {
         variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
-        todo!()
+        self.root()
+            .serialize_tuple_variant(name, variant_index, variant, len)
     }
 
     fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap> {
-        todo!()
+        self.root().serialize_map(len)
     }
 
     fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
-        self.writer.write_u8(TAG_COMPOUND)?;
-        write_name(&mut self.writer, &self.name)?;
-
-        Ok(self)
+        self.root().serialize_struct(name, len)
     }
 
     fn serialize_struct_variant(
@@ -250,11 +624,93 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<'a, W> {
         variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStructVariant> {
-        todo!()
+        self.root()
+            .serialize_struct_variant(name, variant_index, variant, len)
+    }
+}
+
+/// Writes the elements of a `TAG_List`, all of which need to have the same tag.
+pub struct List<'a, W> {
+    ser: &'a mut Serializer<W>,
+    state: ListState,
+    count: Count,
+}
+
+/// How the element count of a list gets written.
+enum Count {
+    /// Up front, with the first element.
+    Known,
+    /// As a placeholder at this position, patched once the list is done.
+    Patch(u64),
+    /// Up front, with the elements buffered until the list is done.
+    Buffer(Box<Serializer<Cursor<Vec<u8>>>>),
+}
+
+impl<W: Write> List<'_, W> {
+    fn element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        trace_span!("nbt_element", index = self.state.written);
+
+        let header = Header::Element(&mut self.state);
+        match &mut self.count {
+            Count::Buffer(buffer) => value.serialize(ValueSerializer {
+                ser: buffer,
+                header,
+            })?,
+            _ => value.serialize(ValueSerializer {
+                ser: &mut *self.ser,
+                header,
+            })?,
+        }
+        self.state.written += 1;
+
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        match self.state.len {
+            Some(len) if len != self.state.written => {
+                return Err(ErrorKind::ListLengthMismatch {
+                    expected: len,
+                    found: self.state.written,
+                }
+                .into());
+            }
+            _ => {}
+        }
+        // without elements nothing has written the list's header yet
+        if self.state.tag.is_none() {
+            self.ser.writer.write_u8(TAG_END)?;
+            self.ser.writer.write_i32::<BigEndian>(0)?;
+            return Ok(());
+        }
+
+        // the count follows the element tag
+        let count = i32::try_from(self.state.written)?.to_be_bytes();
+        match self.count {
+            Count::Known => {}
+            Count::Patch(start) => {
+                let seek = self.ser.seek.expect("patching requires a seekable writer");
+                let writer = &mut self.ser.writer;
+                let end = seek(writer, SeekFrom::Current(0))?;
+                seek(writer, SeekFrom::Start(start + 1))?;
+                writer.write_all(&count)?;
+                seek(writer, SeekFrom::Start(end))?;
+            }
+            Count::Buffer(buffer) => {
+                let mut buffer = buffer.into_inner().into_inner();
+                buffer[1..5].copy_from_slice(&count);
+                self.ser.writer.write_all(&buffer)?;
+            }
+        }
+
+        Ok(())
     }
 }
 
-impl<'a, W: Write> ser::SerializeSeq for &'a mut Serializer<'a, W> {
+impl<W: Write> ser::SerializeSeq for List<'_, W> {
     type Ok = ();
     type Error = Error;
 
@@ -262,15 +718,15 @@ impl<'a, W: Write> ser::SerializeSeq for &'a mut Serializer<'a, W> {
     where
         T: ?Sized + Serialize,
     {
-        Ok(())
+        self.element(value)
     }
 
     fn end(self) -> Result<Self::Ok> {
-        Ok(())
+        self.finish()
     }
 }
 
-impl<'a, W: Write> ser::SerializeTuple for &'a mut Serializer<'a, W> {
+impl<W: Write> ser::SerializeTuple for List<'_, W> {
     type Ok = ();
     type Error = Error;
 
@@ -278,60 +734,114 @@ impl<'a, W: Write> ser::SerializeTuple for &'a mut Serializer<'a, W> {
     where
         T: ?Sized + Serialize,
     {
-        todo!()
+        self.element(value)
     }
 
     fn end(self) -> std::prelude::v1::Result<Self::Ok, Self::Error> {
-        todo!()
+        self.finish()
     }
 }
 
-impl<'a, W: Write> ser::SerializeMap for &'a mut Serializer<'a, W> {
+impl<W: Write> ser::SerializeTupleStruct for List<'_, W> {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_key<T>(&mut self, key: &T) -> std::prelude::v1::Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, value: &T) -> std::prelude::v1::Result<(), Self::Error>
     where
         T: ?Sized + Serialize,
     {
-        todo!()
+        self.element(value)
     }
 
-    fn serialize_value<T>(&mut self, value: &T) -> std::prelude::v1::Result<(), Self::Error>
+    fn end(self) -> std::prelude::v1::Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+/// Writes the entries of a `TAG_Compound`, named after the struct's fields or the map's keys.
+pub struct Compound<'a, W> {
+    ser: &'a mut Serializer<W>,
+    /// The key of the next map entry, reused between entries.
+    key: String,
+    /// In canonical mode, every entry written so far, including its header.
+    entries: Option<Vec<(String, Vec<u8>)>>,
+}
+
+impl<'a, W: Write> Compound<'a, W> {
+    fn new(ser: &'a mut Serializer<W>) -> Self {
+        let entries = ser.canonical.then(Vec::new);
+
+        Self {
+            ser,
+            key: String::new(),
+            entries,
+        }
+    }
+
+    fn entry<T>(&mut self, key: &str, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        todo!()
+        trace_span!("nbt_field", name = key);
+
+        let Some(entries) = &mut self.entries else {
+            return value.serialize(ValueSerializer {
+                ser: &mut *self.ser,
+                header: Header::Named(key),
+            });
+        };
+        let mut buffer = self.ser.buffer();
+        value.serialize(ValueSerializer {
+            ser: &mut buffer,
+            header: Header::Named(key),
+        })?;
+        entries.push((key.to_string(), buffer.into_inner().into_inner()));
+
+        Ok(())
     }
 
-    fn end(self) -> std::prelude::v1::Result<Self::Ok, Self::Error> {
-        todo!()
+    fn finish(self) -> Result<()> {
+        if let Some(mut entries) = self.entries {
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            for (_, entry) in entries {
+                self.ser.writer.write_all(&entry)?;
+            }
+        }
+        self.ser.writer.write_u8(TAG_END)?;
+
+        Ok(())
     }
 }
 
-impl<'a, W: Write> ser::SerializeStruct for &'a mut Serializer<'a, W> {
+impl<W: Write> ser::SerializeMap for Compound<'_, W> {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T>(
-        &mut self,
-        key: &'static str,
-        value: &T,
-    ) -> std::prelude::v1::Result<(), Self::Error>
+    fn serialize_key<T>(&mut self, key: &T) -> std::prelude::v1::Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.key.clear();
+        key.serialize(KeySerializer { key: &mut self.key })
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> std::prelude::v1::Result<(), Self::Error>
     where
         T: ?Sized + Serialize,
     {
-        let mut serializer = Serializer::new(key.to_owned(), self.writer);
-        value.serialize(&mut serializer)
+        let key = std::mem::take(&mut self.key);
+        let result = self.entry(&key, value);
+        self.key = key;
+
+        result
     }
 
     fn end(self) -> std::prelude::v1::Result<Self::Ok, Self::Error> {
-        self.writer.write_u8(TAG_END)?;
-        Ok(())
+        self.finish()
     }
 }
 
-impl<'a, W: Write> ser::SerializeStructVariant for &'a mut Serializer<'a, W> {
+impl<W: Write> ser::SerializeStruct for Compound<'_, W> {
     type Ok = ();
     type Error = Error;
 
@@ -343,49 +853,206 @@ impl<'a, W: Write> ser::SerializeStructVariant for &'a mut Serializer<'a, W> {
     where
         T: ?Sized + Serialize,
     {
-        todo!()
+        self.entry(key, value)
     }
 
     fn end(self) -> std::prelude::v1::Result<Self::Ok, Self::Error> {
-        todo!()
+        self.finish()
     }
 }
 
-impl<'a, W: Write> ser::SerializeTupleStruct for &'a mut Serializer<'a, W> {
+/// Writes map keys into a string. Strings and chars are taken as they are, integers are
+/// formatted in decimal.
+struct KeySerializer<'a> {
+    key: &'a mut String,
+}
+
+impl KeySerializer<'_> {
+    fn integer(self, v: impl std::fmt::Display) -> Result<()> {
+        use std::fmt::Write;
+
+        write!(self.key, "{}", v).map_err(<Error as ser::Error>::custom)
+    }
+}
+
+fn key_must_be_a_string() -> Error {
+    ErrorKind::KeyMustBeAString.into()
+}
+
+impl ser::Serializer for KeySerializer<'_> {
     type Ok = ();
-    type Error = Error;
 
-    fn serialize_field<T>(&mut self, value: &T) -> std::prelude::v1::Result<(), Self::Error>
+    type Error = crate::error::Error;
+
+    type SerializeSeq = ser::Impossible<(), Error>;
+
+    type SerializeTuple = ser::Impossible<(), Error>;
+
+    type SerializeTupleStruct = ser::Impossible<(), Error>;
+
+    type SerializeTupleVariant = ser::Impossible<(), Error>;
+
+    type SerializeMap = ser::Impossible<(), Error>;
+
+    type SerializeStruct = ser::Impossible<(), Error>;
+
+    type SerializeStructVariant = ser::Impossible<(), Error>;
+
+    fn serialize_bool(self, _v: bool) -> Result<Self::Ok> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok> {
+        self.integer(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok> {
+        self.integer(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok> {
+        self.integer(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok> {
+        self.integer(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok> {
+        self.integer(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok> {
+        self.integer(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok> {
+        self.integer(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok> {
+        self.integer(v)
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<Self::Ok> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<Self::Ok> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok> {
+        self.key.push(v);
+        Ok(())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok> {
+        self.key.push_str(v);
+        Ok(())
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok>
     where
         T: ?Sized + Serialize,
     {
-        todo!()
+        value.serialize(self)
     }
 
-    fn end(self) -> std::prelude::v1::Result<Self::Ok, Self::Error> {
-        todo!()
+    fn serialize_unit(self) -> Result<Self::Ok> {
+        Err(key_must_be_a_string())
     }
-}
 
-impl<'a, W: Write> ser::SerializeTupleVariant for &'a mut Serializer<'a, W> {
-    type Ok = ();
-    type Error = Error;
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok> {
+        Err(key_must_be_a_string())
+    }
 
-    fn serialize_field<T>(&mut self, value: &T) -> std::prelude::v1::Result<(), Self::Error>
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<Self::Ok>
     where
         T: ?Sized + Serialize,
     {
-        todo!()
+        value.serialize(self)
     }
 
-    fn end(self) -> std::prelude::v1::Result<Self::Ok, Self::Error> {
-        todo!()
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(key_must_be_a_string())
     }
 }
 
 // ---- Helper functions ---------------------------------------------------------------------------
-pub fn write_name<W: Write>(w: &mut W, name: &str) -> std::io::Result<()> {
-    w.write_u16::<BigEndian>(name.len() as u16)?;
-    w.write_all(name.as_bytes())
-}
+pub fn write_name<W: Write>(w: &mut W, name: &str) -> Result<()> {
+    w.write_u16::<BigEndian>(u16::try_from(name.len())?)?;
+    w.write_all(name.as_bytes())?;
 
+    Ok(())
+}
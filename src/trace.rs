@@ -0,0 +1,17 @@
+//! Thin wrappers around `tracing` that compile to nothing unless the `tracing` feature is on.
+
+/// Enters a trace-level span until the end of the enclosing block.
+macro_rules! trace_span {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        let _span = ::tracing::trace_span!($($arg)*).entered();
+    };
+}
+
+/// Emits a trace-level event.
+macro_rules! trace_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::trace!($($arg)*);
+    };
+}
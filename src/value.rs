@@ -0,0 +1,194 @@
+//! An in-memory NBT tree, for data whose layout isn't known up front.
+
+use std::fmt;
+
+use serde::{
+    de::{self, MapAccess, SeqAccess, Visitor},
+    Deserialize, Serialize,
+};
+
+use crate::nbt::{Tag, INT_ARRAY_TOKEN, LONG_ARRAY_TOKEN};
+
+/// The entries of a `TAG_Compound`.
+///
+/// With the `indexmap` feature this is an `IndexMap` that keeps entries in the order they were
+/// read or inserted, so a file read into a [`Value`] and written back unchanged comes out byte
+/// for byte the same. Otherwise it's a `HashMap` and the order is arbitrary.
+#[cfg(feature = "indexmap")]
+pub type Compound = indexmap::IndexMap<String, Value>;
+#[cfg(not(feature = "indexmap"))]
+pub type Compound = std::collections::HashMap<String, Value>;
+
+/// Any NBT value.
+///
+/// Values round-trip through the [`Serializer`](crate::Serializer) and
+/// [`Deserializer`](crate::Deserializer) with their tags intact, including int and long arrays.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<u8>),
+    String(String),
+    List(Vec<Value>),
+    Compound(Compound),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Value {
+    pub fn tag(&self) -> Tag {
+        match self {
+            Value::Byte(_) => Tag::Byte,
+            Value::Short(_) => Tag::Short,
+            Value::Int(_) => Tag::Int,
+            Value::Long(_) => Tag::Long,
+            Value::Float(_) => Tag::Float,
+            Value::Double(_) => Tag::Double,
+            Value::ByteArray(_) => Tag::ByteArray,
+            Value::String(_) => Tag::String,
+            Value::List(_) => Tag::List,
+            Value::Compound(_) => Tag::Compound,
+            Value::IntArray(_) => Tag::IntArray,
+            Value::LongArray(_) => Tag::LongArray,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&Compound> {
+        match self {
+            Value::Compound(compound) => Some(compound),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Value]> {
+        match self {
+            Value::List(list) => Some(list),
+            _ => None,
+        }
+    }
+}
+
+/// Removes `key` from `compound`, keeping the order of the remaining entries.
+pub(crate) fn remove_entry(compound: &mut Compound, key: &str) -> Option<Value> {
+    #[cfg(feature = "indexmap")]
+    return compound.shift_remove(key);
+    #[cfg(not(feature = "indexmap"))]
+    return compound.remove(key);
+}
+
+impl Serialize for Value {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Byte(v) => serializer.serialize_i8(*v),
+            Value::Short(v) => serializer.serialize_i16(*v),
+            Value::Int(v) => serializer.serialize_i32(*v),
+            Value::Long(v) => serializer.serialize_i64(*v),
+            Value::Float(v) => serializer.serialize_f32(*v),
+            Value::Double(v) => serializer.serialize_f64(*v),
+            Value::ByteArray(v) => serializer.serialize_bytes(v),
+            Value::String(v) => serializer.serialize_str(v),
+            Value::List(v) => v.serialize(serializer),
+            Value::Compound(v) => v.serialize(serializer),
+            Value::IntArray(v) => serializer.serialize_newtype_struct(INT_ARRAY_TOKEN, v),
+            Value::LongArray(v) => serializer.serialize_newtype_struct(LONG_ARRAY_TOKEN, v),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an NBT value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Byte(v as i8))
+    }
+
+    fn visit_i8<E>(self, v: i8) -> Result<Value, E> {
+        Ok(Value::Byte(v))
+    }
+
+    fn visit_i16<E>(self, v: i16) -> Result<Value, E> {
+        Ok(Value::Short(v))
+    }
+
+    fn visit_i32<E>(self, v: i32) -> Result<Value, E> {
+        Ok(Value::Int(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::Long(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Value, E> {
+        i64::try_from(v)
+            .map(Value::Long)
+            .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(v), &self))
+    }
+
+    fn visit_f32<E>(self, v: f32) -> Result<Value, E> {
+        Ok(Value::Float(v))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::Double(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::String(v.to_owned()))
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Value, E> {
+        Ok(Value::String(v))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Value, E> {
+        Ok(Value::ByteArray(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Value, E> {
+        Ok(Value::ByteArray(v))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut list = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(value) = seq.next_element()? {
+            list.push(value);
+        }
+
+        Ok(Value::List(list))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut compound = Compound::new();
+        let Some(key) = map.next_key::<String>()? else {
+            return Ok(Value::Compound(compound));
+        };
+
+        match key.as_str() {
+            INT_ARRAY_TOKEN => return Ok(Value::IntArray(map.next_value()?)),
+            LONG_ARRAY_TOKEN => return Ok(Value::LongArray(map.next_value()?)),
+            _ => {}
+        }
+        compound.insert(key, map.next_value()?);
+        while let Some((key, value)) = map.next_entry()? {
+            compound.insert(key, value);
+        }
+
+        Ok(Value::Compound(compound))
+    }
+}
@@ -0,0 +1,254 @@
+//! Save directories, with the region files of each dimension.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+
+use crate::{
+    error::Result,
+    region::{self, region_coords, RegionReader, CHUNKS},
+};
+
+/// The kinds of region files, each kept in its own folder of a dimension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Storage {
+    /// Terrain, in `region/`.
+    Region,
+    /// Entities, in `entities/` since 1.17.
+    Entities,
+    /// Points of interest like beds and workstations, in `poi/`.
+    Poi,
+}
+
+impl Storage {
+    pub fn dir_name(self) -> &'static str {
+        match self {
+            Storage::Region => "region",
+            Storage::Entities => "entities",
+            Storage::Poi => "poi",
+        }
+    }
+}
+
+/// A dimension of a world and the directory its folders are in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dimension {
+    /// The dimension's id, e.g. `minecraft:the_nether`.
+    pub id: String,
+    pub path: PathBuf,
+}
+
+impl Dimension {
+    /// The region files in the folder for `storage`, with their region coordinates, sorted by
+    /// coordinates. A missing folder has no region files.
+    pub fn region_files(&self, storage: Storage) -> Result<Vec<(i32, i32, PathBuf)>> {
+        let entries = match fs::read_dir(self.path.join(storage.dir_name())) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut files = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if let Some((x, z)) = region_coords(&path) {
+                files.push((x, z, path));
+            }
+        }
+        files.sort();
+
+        Ok(files)
+    }
+}
+
+/// A save directory.
+///
+/// The overworld is stored in the save directory itself, the nether in `DIM-1`, the end in `DIM1`
+/// and dimensions added by data packs in `dimensions/<namespace>/<path>`.
+#[derive(Debug, Clone)]
+pub struct World {
+    path: PathBuf,
+    dimensions: Vec<Dimension>,
+}
+
+impl World {
+    /// Opens the save directory at `path` and finds its dimensions.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        // fails early if the directory is missing or unreadable
+        fs::read_dir(&path)?;
+
+        let mut dimensions = vec![Dimension {
+            id: "minecraft:overworld".to_string(),
+            path: path.clone(),
+        }];
+
+        for (dir, id) in [
+            ("DIM-1", "minecraft:the_nether"),
+            ("DIM1", "minecraft:the_end"),
+        ] {
+            if path.join(dir).is_dir() {
+                dimensions.push(Dimension {
+                    id: id.to_string(),
+                    path: path.join(dir),
+                });
+            }
+        }
+
+        let custom = match fs::read_dir(path.join("dimensions")) {
+            Ok(namespaces) => namespaces.collect::<io::Result<Vec<_>>>()?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut found = Vec::new();
+        for namespace in custom {
+            if !namespace.file_type()?.is_dir() {
+                continue;
+            }
+            let mut names = Vec::new();
+            find_dimensions(&namespace.path(), "", &mut names)?;
+            for (name, dir) in names {
+                found.push(Dimension {
+                    id: format!("{}:{}", namespace.file_name().to_string_lossy(), name),
+                    path: dir,
+                });
+            }
+        }
+        found.sort_by(|a, b| a.id.cmp(&b.id));
+        dimensions.extend(found);
+
+        Ok(World { path, dimensions })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The dimensions found, the vanilla ones first.
+    pub fn dimensions(&self) -> &[Dimension] {
+        &self.dimensions
+    }
+
+    pub fn dimension(&self, id: &str) -> Option<&Dimension> {
+        self.dimensions.iter().find(|dimension| dimension.id == id)
+    }
+
+    /// Iterates over every chunk in the folder for `storage` of every dimension, with absolute
+    /// chunk coordinates.
+    ///
+    /// Region files are opened one at a time and chunks are deserialized one at a time, so the
+    /// memory used doesn't depend on the size of the world.
+    pub fn chunks<T: DeserializeOwned>(&self, storage: Storage) -> Result<WorldChunks<'_, T>> {
+        Ok(WorldChunks {
+            files: self.region_files(storage)?.into_iter(),
+            current: None,
+            marker: PhantomData,
+        })
+    }
+
+    /// Like [`World::chunks`], but with region files spread over rayon's thread pool. Each
+    /// thread reads one region file at a time, in no particular order.
+    #[cfg(feature = "rayon")]
+    pub fn par_chunks<T: DeserializeOwned + Send>(
+        &self,
+        storage: Storage,
+    ) -> Result<impl rayon::iter::ParallelIterator<Item = Result<(&Dimension, i32, i32, T)>>> {
+        use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+        let files = self.region_files(storage)?;
+        Ok(files.into_par_iter().flat_map_iter(|file| WorldChunks {
+            files: vec![file].into_iter(),
+            current: None,
+            marker: PhantomData,
+        }))
+    }
+
+    /// The region files of all dimensions.
+    fn region_files(&self, storage: Storage) -> Result<Vec<RegionFile<'_>>> {
+        let mut files = Vec::new();
+        for dimension in &self.dimensions {
+            for (x, z, path) in dimension.region_files(storage)? {
+                files.push(RegionFile {
+                    dimension,
+                    x,
+                    z,
+                    path,
+                });
+            }
+        }
+
+        Ok(files)
+    }
+}
+
+/// Collects the dimensions below `dir`, whose path within the namespace may contain slashes.
+/// Directories holding any of the storage folders are dimensions.
+fn find_dimensions(dir: &Path, name: &str, found: &mut Vec<(String, PathBuf)>) -> Result<()> {
+    let storages = [Storage::Region, Storage::Entities, Storage::Poi];
+    if !name.is_empty() && storages.iter().any(|s| dir.join(s.dir_name()).is_dir()) {
+        found.push((name.to_string(), dir.to_path_buf()));
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            let child = entry.file_name().to_string_lossy().into_owned();
+            let child = match name {
+                "" => child,
+                name => format!("{}/{}", name, child),
+            };
+            find_dimensions(&entry.path(), &child, found)?;
+        }
+    }
+
+    Ok(())
+}
+
+struct RegionFile<'a> {
+    dimension: &'a Dimension,
+    x: i32,
+    z: i32,
+    path: PathBuf,
+}
+
+/// The chunks of a world, see [`World::chunks`].
+pub struct WorldChunks<'a, T> {
+    files: std::vec::IntoIter<RegionFile<'a>>,
+    /// The region file being read and the index of the next chunk in it.
+    current: Option<(RegionFile<'a>, RegionReader<BufReader<File>>, usize)>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<'a, T: DeserializeOwned> Iterator for WorldChunks<'a, T> {
+    type Item = Result<(&'a Dimension, i32, i32, T)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Some((file, reader, index)) = &mut self.current else {
+                let file = self.files.next()?;
+                match RegionReader::open(&file.path) {
+                    Ok(reader) => self.current = Some((file, reader, 0)),
+                    Err(e) => return Some(Err(e)),
+                }
+                continue;
+            };
+
+            while *index < CHUNKS {
+                let (x, z) = region::position(*index);
+                *index += 1;
+
+                let (x, z) = (file.x * 32 + x, file.z * 32 + z);
+                match reader.chunk(x, z) {
+                    Ok(Some(chunk)) => return Some(Ok((file.dimension, x, z, chunk))),
+                    Ok(None) => {}
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+            self.current = None;
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! Push-based writing of NBT one value at a time, for trees too large to be built in memory.
+
+use byteorder::{BigEndian, WriteBytesExt};
+use serde::Serialize;
+use std::io::Write;
+
+use crate::{
+    error::{ErrorKind, Result},
+    nbt::*,
+    options::UnsignedPolicy,
+    ser::{Header, ListState, Serializer, ValueSerializer},
+};
+
+/// The compounds and lists the writer is currently inside of.
+enum Frame {
+    Compound,
+    List { len: usize, state: ListState },
+}
+
+/// Writes NBT through explicit calls like [`begin_compound`](NbtWriter::begin_compound),
+/// [`write_int`](NbtWriter::write_int) and [`end_compound`](NbtWriter::end_compound).
+///
+/// The writer checks that compounds and lists are closed in the right order and that lists get
+/// exactly the announced number of elements of the announced tag. Subtrees that are in memory can
+/// be written with [`write_value`](NbtWriter::write_value) through the [`Serializer`].
+///
+/// Every value takes a name, which is ignored for list elements.
+pub struct NbtWriter<W> {
+    ser: Serializer<W>,
+    stack: Vec<Frame>,
+}
+
+impl<W: Write> NbtWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            ser: Serializer::new(writer),
+            stack: Vec::new(),
+        }
+    }
+
+    /// Sets how unsigned integers in [`write_value`](NbtWriter::write_value) are mapped onto NBT
+    /// tags, see [`UnsignedPolicy`].
+    pub fn with_unsigned_policy(mut self, policy: UnsignedPolicy) -> Self {
+        self.ser = self.ser.with_unsigned_policy(policy);
+        self
+    }
+
+    /// Returns the underlying writer, failing if a compound or list is still open.
+    pub fn finish(self) -> Result<W> {
+        if !self.stack.is_empty() {
+            return Err(ErrorKind::ExpectedEnd.into());
+        }
+
+        Ok(self.ser.into_inner())
+    }
+
+    pub fn begin_compound(&mut self, name: &str) -> Result<()> {
+        self.value(name, |mut value| value.write_header(TAG_COMPOUND))?;
+        self.stack.push(Frame::Compound);
+
+        Ok(())
+    }
+
+    pub fn end_compound(&mut self) -> Result<()> {
+        match self.stack.last() {
+            Some(Frame::Compound) => {
+                self.stack.pop();
+                self.ser.writer.write_u8(TAG_END)?;
+                Ok(())
+            }
+            _ => Err(self.unbalanced(Tag::Compound)),
+        }
+    }
+
+    /// Starts a list of `len` elements with the given tag.
+    pub fn begin_list(&mut self, name: &str, tag: Tag, len: usize) -> Result<()> {
+        self.value(name, |mut value| {
+            value.write_header(TAG_LIST)?;
+            value.ser.writer.write_u8(tag.into())?;
+            value
+                .ser
+                .writer
+                .write_i32::<BigEndian>(i32::try_from(len)?)?;
+            Ok(())
+        })?;
+        self.stack.push(Frame::List {
+            len,
+            state: ListState {
+                len: Some(len),
+                tag: Some(tag.into()),
+                written: 0,
+            },
+        });
+
+        Ok(())
+    }
+
+    /// Closes the innermost list, failing if it didn't get all of its elements.
+    pub fn end_list(&mut self) -> Result<()> {
+        match self.stack.last() {
+            Some(Frame::List { len, state }) if state.written != *len => {
+                Err(ErrorKind::ListLengthMismatch {
+                    expected: *len,
+                    found: state.written,
+                }
+                .into())
+            }
+            Some(Frame::List { .. }) => {
+                self.stack.pop();
+                Ok(())
+            }
+            _ => Err(self.unbalanced(Tag::List)),
+        }
+    }
+
+    pub fn write_byte(&mut self, name: &str, v: i8) -> Result<()> {
+        self.value(name, |value| v.serialize(value))
+    }
+
+    pub fn write_short(&mut self, name: &str, v: i16) -> Result<()> {
+        self.value(name, |value| v.serialize(value))
+    }
+
+    pub fn write_int(&mut self, name: &str, v: i32) -> Result<()> {
+        self.value(name, |value| v.serialize(value))
+    }
+
+    pub fn write_long(&mut self, name: &str, v: i64) -> Result<()> {
+        self.value(name, |value| v.serialize(value))
+    }
+
+    pub fn write_float(&mut self, name: &str, v: f32) -> Result<()> {
+        self.value(name, |value| v.serialize(value))
+    }
+
+    pub fn write_double(&mut self, name: &str, v: f64) -> Result<()> {
+        self.value(name, |value| v.serialize(value))
+    }
+
+    pub fn write_string(&mut self, name: &str, v: &str) -> Result<()> {
+        self.value(name, |value| v.serialize(value))
+    }
+
+    pub fn write_byte_array(&mut self, name: &str, v: &[u8]) -> Result<()> {
+        self.value(name, |value| serde::Serializer::serialize_bytes(value, v))
+    }
+
+    pub fn write_int_array(&mut self, name: &str, v: &[i32]) -> Result<()> {
+        self.value(name, |mut value| {
+            value.write_header(TAG_INT_ARRAY)?;
+            let writer = &mut value.ser.writer;
+            writer.write_i32::<BigEndian>(i32::try_from(v.len())?)?;
+            for x in v {
+                writer.write_i32::<BigEndian>(*x)?;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn write_long_array(&mut self, name: &str, v: &[i64]) -> Result<()> {
+        self.value(name, |mut value| {
+            value.write_header(TAG_LONG_ARRAY)?;
+            let writer = &mut value.ser.writer;
+            writer.write_i32::<BigEndian>(i32::try_from(v.len())?)?;
+            for x in v {
+                writer.write_i64::<BigEndian>(*x)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Writes a whole subtree through the [`Serializer`].
+    pub fn write_value<T: ?Sized + Serialize>(&mut self, name: &str, value: &T) -> Result<()> {
+        self.value(name, |ser| value.serialize(ser))
+    }
+
+    /// Writes the next value through `f`, counting it against the innermost list if there is
+    /// one.
+    fn value<F>(&mut self, name: &str, f: F) -> Result<()>
+    where
+        F: FnOnce(ValueSerializer<'_, '_, W>) -> Result<()>,
+    {
+        match self.stack.last_mut() {
+            Some(Frame::List { len, state }) => {
+                if state.written == *len {
+                    return Err(ErrorKind::ListLengthMismatch {
+                        expected: *len,
+                        found: *len + 1,
+                    }
+                    .into());
+                }
+                f(ValueSerializer {
+                    ser: &mut self.ser,
+                    header: Header::Element(state),
+                })?;
+                state.written += 1;
+
+                Ok(())
+            }
+            _ => f(ValueSerializer {
+                ser: &mut self.ser,
+                header: Header::Named(name),
+            }),
+        }
+    }
+
+    fn unbalanced(&self, found: Tag) -> crate::Error {
+        let open = self.stack.last().map(|frame| match frame {
+            Frame::Compound => Tag::Compound,
+            Frame::List { .. } => Tag::List,
+        });
+
+        ErrorKind::UnbalancedEnd { open, found }.into()
+    }
+}
@@ -1,11 +1,16 @@
 use std::io::Cursor;
 
 use rnbt::read_nbt;
+use serde::{Deserialize, Serialize};
 use serde_nbt::*;
 
-fn test_back_and_forth<T: serde::Serialize + serde::de::DeserializeOwned + std::fmt::Debug + PartialEq>(value: T) {
+fn test_back_and_forth<
+    T: serde::Serialize + serde::de::DeserializeOwned + std::fmt::Debug + PartialEq,
+>(
+    value: T,
+) {
     let mut output = Vec::new();
-    to_writer(&mut output, &value, "TestStruct".to_string()).unwrap();
+    to_writer(&mut output, &value, "TestStruct").unwrap();
     let expected: T = from_bytes(&output).unwrap();
 
     assert_eq!(expected, value);
@@ -24,11 +29,10 @@ fn try_simple_struct() {
         b: "hello".to_string(),
     };
     let mut output = Vec::new();
-    to_writer(&mut output, &simple, "TestStruct".to_string()).unwrap();
+    to_writer(&mut output, &simple, "TestStruct").unwrap();
 
     println!("{:?}", output);
 
-
     // try as nbt
     //
     let mut cursor = Cursor::new(output.clone());
@@ -37,6 +41,2419 @@ fn try_simple_struct() {
 
     let expected: TestStruct = from_bytes(&output).unwrap();
 
-
     assert_eq!(expected, simple);
 }
+
+fn round_trip_with_policy<T>(value: &T, policy: UnsignedPolicy) -> Result<T>
+where
+    T: serde::Serialize + serde::de::DeserializeOwned,
+{
+    let mut output = Vec::new();
+    let mut serializer = Serializer::new(&mut output).with_unsigned_policy(policy);
+    serializer.serialize_named("TestStruct", value)?;
+
+    let mut deserializer =
+        Deserializer::from_reader(Cursor::new(output)).with_unsigned_policy(policy);
+    T::deserialize(&mut deserializer)
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+struct Unsigned {
+    a: u8,
+    b: u16,
+    c: u32,
+    d: u64,
+}
+
+#[test]
+fn unsigned_widen_round_trips() {
+    let value = Unsigned {
+        a: u8::MAX,
+        b: u16::MAX,
+        c: u32::MAX,
+        d: i64::MAX as u64,
+    };
+    assert_eq!(
+        round_trip_with_policy(&value, UnsignedPolicy::Widen).unwrap(),
+        value
+    );
+
+    test_back_and_forth(value);
+}
+
+#[test]
+fn unsigned_widen_keeps_u8_as_byte() {
+    #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+    struct Byte {
+        a: u8,
+    }
+
+    // how earlier versions wrote a `u8`
+    let mut bytes = named(10, "");
+    bytes.extend(named(1, "a"));
+    bytes.extend([200, 0]);
+
+    assert_eq!(from_bytes::<Byte>(&bytes).unwrap(), Byte { a: 200 });
+    let mut output = Vec::new();
+    to_writer(&mut output, &Byte { a: 200 }, "").unwrap();
+    assert_eq!(output, bytes);
+}
+
+#[test]
+fn unsigned_widen_rejects_large_u64() {
+    let value = Unsigned {
+        a: 0,
+        b: 0,
+        c: 0,
+        d: u64::MAX,
+    };
+    assert!(round_trip_with_policy(&value, UnsignedPolicy::Widen).is_err());
+}
+
+#[test]
+fn unsigned_reinterpret_round_trips() {
+    let value = Unsigned {
+        a: u8::MAX,
+        b: u16::MAX,
+        c: u32::MAX,
+        d: u64::MAX,
+    };
+    assert_eq!(
+        round_trip_with_policy(&value, UnsignedPolicy::Reinterpret).unwrap(),
+        value
+    );
+}
+
+#[test]
+fn unsigned_reinterpret_uses_same_width_tags() {
+    #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+    struct Signed {
+        a: i16,
+        b: i32,
+        c: i64,
+    }
+    #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+    struct Reinterpreted {
+        a: u16,
+        b: u32,
+        c: u64,
+    }
+
+    let mut output = Vec::new();
+    let mut serializer = Serializer::new(&mut output);
+    Signed {
+        a: -1,
+        b: -2,
+        c: -3,
+    }
+    .serialize(&mut serializer)
+    .unwrap();
+
+    let mut deserializer = Deserializer::from_reader(Cursor::new(output))
+        .with_unsigned_policy(UnsignedPolicy::Reinterpret);
+    let value = Reinterpreted::deserialize(&mut deserializer).unwrap();
+
+    assert_eq!(
+        value,
+        Reinterpreted {
+            a: u16::MAX,
+            b: u32::MAX - 1,
+            c: u64::MAX - 2
+        }
+    );
+}
+
+#[test]
+fn unsigned_reject_fails() {
+    let value = Unsigned {
+        a: 1,
+        b: 2,
+        c: 3,
+        d: 4,
+    };
+    let err = round_trip_with_policy(&value, UnsignedPolicy::Reject).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::UnsignedRejected));
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+struct Narrow {
+    a: i8,
+    b: i16,
+    c: bool,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+struct Wide {
+    a: i64,
+    b: i32,
+    c: i32,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+struct Floats {
+    a: f32,
+    b: f64,
+}
+
+fn write_test_struct<T: serde::Serialize>(value: &T) -> Vec<u8> {
+    let mut output = Vec::new();
+    to_writer(&mut output, value, "TestStruct").unwrap();
+    output
+}
+
+fn read_with_coercion<T: serde::de::DeserializeOwned>(
+    bytes: Vec<u8>,
+    coercion: Coercion,
+) -> Result<T> {
+    let mut deserializer = Deserializer::from_reader(Cursor::new(bytes)).with_coercion(coercion);
+    T::deserialize(&mut deserializer)
+}
+
+#[test]
+fn i8_round_trips_as_byte() {
+    test_back_and_forth(Narrow {
+        a: -5,
+        b: 300,
+        c: true,
+    });
+}
+
+#[test]
+fn strict_reports_tag_mismatch() {
+    let bytes = write_test_struct(&Narrow {
+        a: 1,
+        b: 2,
+        c: false,
+    });
+
+    let err = read_with_coercion::<Wide>(bytes, Coercion::Strict).unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::UnexpectedTag {
+            expected: Tag::Long,
+            found: Tag::Byte
+        }
+    ));
+}
+
+#[test]
+fn lenient_accepts_any_fitting_integer() {
+    let bytes = write_test_struct(&Narrow {
+        a: 1,
+        b: 2,
+        c: true,
+    });
+    let wide: Wide = read_with_coercion(bytes, Coercion::Lenient).unwrap();
+    assert_eq!(wide, Wide { a: 1, b: 2, c: 1 });
+
+    let bytes = write_test_struct(&Wide { a: 1, b: -2, c: 0 });
+    let narrow: Narrow = read_with_coercion(bytes, Coercion::Lenient).unwrap();
+    assert_eq!(
+        narrow,
+        Narrow {
+            a: 1,
+            b: -2,
+            c: false
+        }
+    );
+}
+
+#[test]
+fn lenient_checks_ranges() {
+    let bytes = write_test_struct(&Wide {
+        a: 1000,
+        b: 0,
+        c: 0,
+    });
+    assert!(read_with_coercion::<Narrow>(bytes, Coercion::Lenient).is_err());
+
+    let bytes = write_test_struct(&Wide { a: 0, b: 0, c: 2 });
+    assert!(read_with_coercion::<Narrow>(bytes, Coercion::Lenient).is_err());
+}
+
+#[test]
+fn lenient_accepts_any_float() {
+    #[derive(Debug, serde::Serialize)]
+    struct Swapped {
+        a: f64,
+        b: f32,
+    }
+
+    let bytes = write_test_struct(&Swapped { a: 1.5, b: 2.5 });
+    assert!(read_with_coercion::<Floats>(bytes.clone(), Coercion::Strict).is_err());
+    let floats: Floats = read_with_coercion(bytes, Coercion::Lenient).unwrap();
+    assert_eq!(floats, Floats { a: 1.5, b: 2.5 });
+}
+
+fn named(tag: u8, name: &str) -> Vec<u8> {
+    let mut bytes = vec![tag];
+    bytes.extend_from_slice(&(name.len() as u16).to_be_bytes());
+    bytes.extend_from_slice(name.as_bytes());
+    bytes
+}
+
+#[test]
+fn errors_report_path_and_offset() {
+    #[derive(Debug, serde::Deserialize)]
+    #[allow(dead_code)]
+    #[serde(rename_all = "PascalCase")]
+    struct Section {
+        block_states: i32,
+    }
+    #[derive(Debug, serde::Deserialize)]
+    #[allow(dead_code)]
+    #[serde(rename_all = "PascalCase")]
+    struct Level {
+        sections: Vec<Section>,
+    }
+    #[derive(Debug, serde::Deserialize)]
+    #[allow(dead_code)]
+    #[serde(rename_all = "PascalCase")]
+    struct Root {
+        level: Level,
+    }
+
+    let mut bytes = named(10, "");
+    bytes.extend(named(10, "Level"));
+    bytes.extend(named(9, "Sections"));
+    bytes.push(10);
+    bytes.extend_from_slice(&4i32.to_be_bytes());
+    for i in 0..4 {
+        if i == 3 {
+            bytes.extend(named(4, "BlockStates"));
+            bytes.extend_from_slice(&1i64.to_be_bytes());
+        } else {
+            bytes.extend(named(3, "BlockStates"));
+            bytes.extend_from_slice(&1i32.to_be_bytes());
+        }
+        bytes.push(0);
+    }
+    bytes.push(0);
+    bytes.push(0);
+
+    let err = from_bytes::<Root>(&bytes).unwrap_err();
+    assert_eq!(
+        err.path(),
+        &[
+            PathSegment::Key("Level".to_string()),
+            PathSegment::Key("Sections".to_string()),
+            PathSegment::Index(3),
+            PathSegment::Key("BlockStates".to_string()),
+        ]
+    );
+    assert_eq!(err.offset(), Some(98));
+    assert_eq!(
+        err.to_string(),
+        "expected TAG_Int, found TAG_Long at Level.Sections[3].BlockStates (offset 98)"
+    );
+}
+
+#[test]
+fn io_errors_keep_their_source() {
+    let bytes = write_test_struct(&Narrow {
+        a: 1,
+        b: 2,
+        c: true,
+    });
+
+    let err = from_bytes::<Narrow>(&bytes[..bytes.len() - 3]).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::Io(_)));
+    assert!(std::error::Error::source(&err).is_some());
+}
+
+#[test]
+fn unknown_fields_are_skipped() {
+    #[derive(Debug, serde::Serialize)]
+    struct Full {
+        a: i8,
+        extra: String,
+        b: i16,
+        c: bool,
+    }
+
+    let bytes = write_test_struct(&Full {
+        a: 1,
+        extra: "ignored".to_string(),
+        b: 2,
+        c: true,
+    });
+    let narrow: Narrow = from_bytes(&bytes).unwrap();
+    assert_eq!(
+        narrow,
+        Narrow {
+            a: 1,
+            b: 2,
+            c: true
+        }
+    );
+}
+
+fn read_with_limits<T: serde::de::DeserializeOwned>(bytes: Vec<u8>, limits: Limits) -> Result<T> {
+    let mut deserializer = Deserializer::from_reader(Cursor::new(bytes)).with_limits(limits);
+    T::deserialize(&mut deserializer)
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[allow(dead_code)]
+struct Blob {
+    #[serde(default)]
+    data: Vec<i8>,
+}
+
+fn nested_compounds(depth: usize) -> Vec<u8> {
+    let mut bytes = named(10, "");
+    for _ in 1..depth {
+        bytes.extend(named(10, "a"));
+    }
+    bytes.extend(vec![0; depth]);
+    bytes
+}
+
+#[test]
+fn depth_limit_applies_to_ignored_values() {
+    assert!(read_with_limits::<Blob>(nested_compounds(512), Limits::default()).is_ok());
+
+    let err = read_with_limits::<Blob>(nested_compounds(513), Limits::default()).unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::DepthLimitExceeded { max: 512 }
+    ));
+}
+
+#[test]
+fn length_limit_fails_before_allocating() {
+    let mut bytes = named(10, "");
+    bytes.extend(named(7, "data"));
+    bytes.extend_from_slice(&i32::MAX.to_be_bytes());
+
+    let limits = Limits {
+        max_len: Some(1024),
+        ..Limits::default()
+    };
+    let err = read_with_limits::<Blob>(bytes.clone(), limits).unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::LengthLimitExceeded {
+            len: 2147483647,
+            max: 1024
+        }
+    ));
+
+    // without limits the bogus length runs into the end of the input instead
+    let err = read_with_limits::<Blob>(bytes, Limits::default()).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::Io(_)));
+}
+
+#[test]
+fn size_limit_counts_all_bytes_read() {
+    let mut bytes = named(10, "");
+    bytes.extend(named(7, "data"));
+    bytes.extend_from_slice(&100i32.to_be_bytes());
+    bytes.extend(vec![1; 100]);
+    bytes.push(0);
+
+    let limits = Limits {
+        max_bytes: Some(bytes.len() as u64),
+        ..Limits::default()
+    };
+    assert!(read_with_limits::<Blob>(bytes.clone(), limits).is_ok());
+
+    let limits = Limits {
+        max_bytes: Some(64),
+        ..Limits::default()
+    };
+    let err = read_with_limits::<Blob>(bytes, limits).unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::SizeLimitExceeded { max: 64 }
+    ));
+}
+
+#[test]
+fn from_slice_borrows_strings_and_bytes() {
+    #[derive(Debug, serde::Deserialize)]
+    struct Borrowed<'a> {
+        name: &'a str,
+        #[serde(borrow)]
+        title: std::borrow::Cow<'a, str>,
+        data: &'a [u8],
+    }
+
+    let mut bytes = named(10, "");
+    bytes.extend(named(8, "name"));
+    bytes.extend_from_slice(&5u16.to_be_bytes());
+    bytes.extend_from_slice(b"hello");
+    bytes.extend(named(8, "title"));
+    bytes.extend_from_slice(&5u16.to_be_bytes());
+    bytes.extend_from_slice(b"world");
+    bytes.extend(named(7, "data"));
+    bytes.extend_from_slice(&3i32.to_be_bytes());
+    bytes.extend_from_slice(&[1, 2, 3]);
+    bytes.push(0);
+
+    let value: Borrowed = from_slice(&bytes).unwrap();
+    assert_eq!(value.name, "hello");
+    assert!(matches!(value.title, std::borrow::Cow::Borrowed("world")));
+    assert_eq!(value.data, &[1, 2, 3]);
+
+    // the same input read through a reader can't be borrowed from
+    let mut deserializer = Deserializer::from_reader(Cursor::new(&bytes));
+    assert!(Borrowed::deserialize(&mut deserializer).is_err());
+}
+
+#[test]
+fn from_reader_takes_owned_and_borrowed_readers() {
+    fn load(path: &std::path::Path) -> Result<Narrow> {
+        let file = std::fs::File::open(path)?;
+        from_reader(std::io::BufReader::new(file))
+    }
+
+    let value = Narrow {
+        a: 1,
+        b: 2,
+        c: true,
+    };
+    let path =
+        std::env::temp_dir().join(format!("serde_nbt_from_reader_{}.nbt", std::process::id()));
+    std::fs::write(&path, write_test_struct(&value)).unwrap();
+    let loaded = load(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.unwrap(), value);
+
+    // a reader borrowed mutably can be used for several values in a row
+    let mut bytes = write_test_struct(&value);
+    bytes.extend(write_test_struct(&Narrow {
+        a: 3,
+        b: 4,
+        c: false,
+    }));
+    let mut cursor = Cursor::new(bytes);
+    let first: Narrow = from_reader(&mut cursor).unwrap();
+    let second: Narrow = from_reader(&mut cursor).unwrap();
+    assert_eq!(first, value);
+    assert_eq!(second.a, 3);
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+struct Section {
+    y: i8,
+    blocks: Vec<i32>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+struct Chunk {
+    name: String,
+    position: (i32, i32),
+    sections: Vec<Section>,
+    inner: Narrow,
+    empty: Vec<i16>,
+}
+
+#[test]
+fn nested_structs_and_lists_round_trip() {
+    test_back_and_forth(Chunk {
+        name: "chunk".to_string(),
+        position: (-3, 7),
+        sections: vec![
+            Section {
+                y: -4,
+                blocks: vec![1, 2, 3],
+            },
+            Section {
+                y: 5,
+                blocks: vec![],
+            },
+        ],
+        inner: Narrow {
+            a: 1,
+            b: 2,
+            c: true,
+        },
+        empty: vec![],
+    });
+}
+
+#[test]
+fn serializer_writes_several_root_values() {
+    let mut serializer = Serializer::new(Vec::new());
+    serializer
+        .serialize_named(
+            "first",
+            &Narrow {
+                a: 1,
+                b: 2,
+                c: true,
+            },
+        )
+        .unwrap();
+    Wide { a: 3, b: 4, c: 5 }
+        .serialize(&mut serializer)
+        .unwrap();
+
+    let mut cursor = Cursor::new(serializer.into_inner());
+    let first: Narrow = from_reader(&mut cursor).unwrap();
+    let second: Wide = from_reader(&mut cursor).unwrap();
+    assert_eq!(first.b, 2);
+    assert_eq!(second, Wide { a: 3, b: 4, c: 5 });
+}
+
+#[test]
+fn mixed_list_elements_are_rejected() {
+    #[derive(serde::Serialize)]
+    struct Mixed {
+        values: (i32, i64),
+    }
+
+    let err = to_writer(Vec::new(), &Mixed { values: (1, 2) }, "TestStruct").unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::IncompatibleListType {
+            expected: Tag::Int,
+            found: Tag::Long
+        }
+    ));
+}
+
+#[test]
+fn missing_list_elements_are_rejected() {
+    #[derive(serde::Serialize)]
+    struct Holes {
+        values: Vec<Option<i32>>,
+        skipped: Option<i32>,
+    }
+
+    for values in [vec![Some(1), None, Some(2)], vec![None]] {
+        let holes = Holes {
+            values,
+            skipped: None,
+        };
+        let err = to_writer(Vec::new(), &holes, "TestStruct").unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::Message(_)));
+    }
+    let holes = Holes {
+        values: vec![Some(1)],
+        skipped: None,
+    };
+    assert!(to_writer(Vec::new(), &holes, "TestStruct").is_ok());
+
+    let mut writer = NbtWriter::new(Vec::new());
+    writer.begin_list("values", Tag::Int, 1).unwrap();
+    assert!(writer.write_value("", &None::<i32>).is_err());
+}
+
+#[test]
+fn nbt_reader_yields_events() {
+    #[derive(serde::Serialize)]
+    struct Root {
+        name: String,
+        sections: Vec<Section>,
+        #[serde(with = "serde_bytes_like")]
+        light: Vec<u8>,
+    }
+
+    mod serde_bytes_like {
+        pub fn serialize<S: serde::Serializer>(v: &[u8], s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_bytes(v)
+        }
+    }
+
+    let bytes = write_test_struct(&Root {
+        name: "chunk".to_string(),
+        sections: vec![Section {
+            y: -4,
+            blocks: vec![7],
+        }],
+        light: vec![1, 2, 3, 4, 5],
+    });
+
+    let mut reader = NbtReader::from_slice(&bytes).with_chunk_len(2);
+    let mut events = Vec::new();
+    while let Some(event) = reader.next().unwrap() {
+        events.push(format!("{:?}", event));
+    }
+
+    assert_eq!(
+        events,
+        [
+            r#"StartCompound(Some("TestStruct"))"#,
+            r#"Field(String, "name")"#,
+            r#"String("chunk")"#,
+            r#"Field(List, "sections")"#,
+            "StartList(Compound, 1)",
+            "StartCompound(None)",
+            r#"Field(Byte, "y")"#,
+            "Byte(-4)",
+            r#"Field(List, "blocks")"#,
+            "StartList(Int, 1)",
+            "Int(7)",
+            "EndList",
+            "EndCompound",
+            "EndList",
+            r#"Field(ByteArray, "light")"#,
+            "StartArray(ByteArray, 5)",
+            "ByteChunk([1, 2])",
+            "ByteChunk([3, 4])",
+            "ByteChunk([5])",
+            "EndArray",
+            "EndCompound",
+        ]
+    );
+}
+
+#[test]
+fn nbt_reader_skips_values() {
+    let bytes = write_test_struct(&Chunk {
+        name: "chunk".to_string(),
+        position: (1, 2),
+        sections: vec![Section {
+            y: 0,
+            blocks: vec![1, 2, 3],
+        }],
+        inner: Narrow {
+            a: 4,
+            b: 5,
+            c: false,
+        },
+        empty: vec![],
+    });
+
+    // read from an io::Read to exercise the copying path as well
+    let mut reader = NbtReader::from_reader(Cursor::new(&bytes));
+    let mut names = Vec::new();
+    while let Some(event) = reader.next().unwrap() {
+        match event {
+            Event::Field(_, name) => {
+                names.push(name.to_string());
+                reader.skip().unwrap();
+            }
+            Event::StartCompound(Some("inner")) => {
+                names.push("inner".to_string());
+                reader.skip().unwrap();
+            }
+            _ => {}
+        }
+    }
+
+    assert_eq!(names, ["name", "position", "sections", "inner", "empty"]);
+    assert_eq!(reader.offset(), bytes.len() as u64);
+}
+
+#[test]
+fn nbt_writer_streams_lists() {
+    let mut writer = NbtWriter::new(Vec::new());
+    writer.begin_compound("TestStruct").unwrap();
+    writer.write_string("name", "chunk").unwrap();
+    writer.write_value("position", &(1, 2)).unwrap();
+    writer.begin_list("sections", Tag::Compound, 3).unwrap();
+    for y in 0..3 {
+        writer.begin_compound("").unwrap();
+        writer.write_byte("y", y).unwrap();
+        writer.begin_list("blocks", Tag::Int, 1).unwrap();
+        writer.write_int("", y as i32 * 10).unwrap();
+        writer.end_list().unwrap();
+        writer.end_compound().unwrap();
+    }
+    writer.end_list().unwrap();
+    writer
+        .write_value(
+            "inner",
+            &Narrow {
+                a: 1,
+                b: 2,
+                c: true,
+            },
+        )
+        .unwrap();
+    writer.begin_list("empty", Tag::Short, 0).unwrap();
+    writer.end_list().unwrap();
+    writer.end_compound().unwrap();
+
+    let chunk: Chunk = from_bytes(&writer.finish().unwrap()).unwrap();
+    assert_eq!(chunk.position, (1, 2));
+    assert_eq!(chunk.sections.len(), 3);
+    assert_eq!(chunk.sections[2].blocks, [20]);
+    assert_eq!(chunk.inner.b, 2);
+}
+
+#[test]
+fn nbt_writer_enforces_structure() {
+    let mut writer = NbtWriter::new(Vec::new());
+    writer.begin_compound("root").unwrap();
+    writer.begin_list("values", Tag::Int, 2).unwrap();
+
+    let err = writer.write_long("", 1).unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::IncompatibleListType {
+            expected: Tag::Int,
+            found: Tag::Long
+        }
+    ));
+
+    writer.write_int("", 1).unwrap();
+    let err = writer.end_list().unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::ListLengthMismatch {
+            expected: 2,
+            found: 1
+        }
+    ));
+    let err = writer.end_compound().unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::UnbalancedEnd {
+            open: Some(Tag::List),
+            found: Tag::Compound
+        }
+    ));
+
+    writer.write_int("", 2).unwrap();
+    assert!(writer.write_int("", 3).is_err());
+    writer.end_list().unwrap();
+    assert!(matches!(
+        writer.finish().unwrap_err().kind(),
+        ErrorKind::ExpectedEnd
+    ));
+}
+
+/// Serializes its filtered rows through `collect_seq`, so neither length is known up front.
+struct Filtered {
+    rows: Vec<Vec<i32>>,
+}
+
+impl serde::Serialize for Filtered {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        struct Evens<'a>(&'a [i32]);
+        impl serde::Serialize for Evens<'_> {
+            fn serialize<S: serde::Serializer>(
+                &self,
+                serializer: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                serializer.collect_seq(self.0.iter().filter(|x| *x % 2 == 0))
+            }
+        }
+
+        struct Rows<'a>(&'a [Vec<i32>]);
+        impl serde::Serialize for Rows<'_> {
+            fn serialize<S: serde::Serializer>(
+                &self,
+                serializer: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                serializer.collect_seq(
+                    self.0
+                        .iter()
+                        .filter(|row| !row.is_empty())
+                        .map(|row| Evens(row)),
+                )
+            }
+        }
+
+        let mut s = serializer.serialize_struct("Filtered", 2)?;
+        s.serialize_field("rows", &Rows(&self.rows))?;
+        s.serialize_field("none", &Evens(&[1, 3]))?;
+        s.end()
+    }
+}
+
+#[derive(Debug, serde::Deserialize, PartialEq)]
+struct Collected {
+    rows: Vec<Vec<i32>>,
+    none: Vec<i32>,
+}
+
+#[test]
+fn unknown_length_sequences_are_buffered_or_patched() {
+    let value = Filtered {
+        rows: vec![vec![1, 2, 3, 4], vec![], vec![6]],
+    };
+    let expected = Collected {
+        rows: vec![vec![2, 4], vec![6]],
+        none: vec![],
+    };
+
+    let buffered = write_test_struct(&value);
+    assert_eq!(from_bytes::<Collected>(&buffered).unwrap(), expected);
+
+    let mut serializer = Serializer::new(Cursor::new(Vec::new())).with_seek();
+    serializer.serialize_named("TestStruct", &value).unwrap();
+    let patched = serializer.into_inner().into_inner();
+    assert_eq!(patched, buffered);
+}
+
+#[test]
+fn from_reader_at_path_reads_a_subtree() {
+    let bytes = write_test_struct(&Chunk {
+        name: "chunk".to_string(),
+        position: (1, 2),
+        sections: vec![
+            Section {
+                y: 0,
+                blocks: vec![1, 2, 3],
+            },
+            Section {
+                y: 1,
+                blocks: vec![4, 5],
+            },
+        ],
+        inner: Narrow {
+            a: 4,
+            b: 5,
+            c: false,
+        },
+        empty: vec![],
+    });
+
+    let inner: Narrow = from_reader_at_path(Cursor::new(&bytes), "inner").unwrap();
+    assert_eq!(inner.b, 5);
+
+    let section: Section = from_reader_at_path(Cursor::new(&bytes), "sections[1]").unwrap();
+    assert_eq!(section.blocks, [4, 5]);
+
+    let block: i32 = from_reader_at_path(Cursor::new(&bytes), "sections[1].blocks[0]").unwrap();
+    assert_eq!(block, 4);
+
+    let err = from_reader_at_path::<_, i32>(Cursor::new(&bytes), "sections[2].y").unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::NotFound));
+    assert_eq!(
+        err.path(),
+        [PathSegment::Key("sections".into()), PathSegment::Index(2)]
+    );
+
+    let err = from_reader_at_path::<_, i64>(Cursor::new(&bytes), "inner.a").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "expected TAG_Long, found TAG_Byte at inner.a (offset 139)"
+    );
+
+    let err = from_reader_at_path::<_, i32>(Cursor::new(&bytes), "sections[x]").unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::InvalidPath(segment) if segment == "sections[x]"));
+}
+
+fn compound<const N: usize>(entries: [(&str, Value); N]) -> Value {
+    Value::Compound(
+        entries
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect(),
+    )
+}
+
+fn inventory() -> Value {
+    let item = |slot: i8, id: &str, count: i8| {
+        compound([
+            ("Slot", Value::Byte(slot)),
+            ("id", Value::String(id.to_string())),
+            ("Count", Value::Byte(count)),
+        ])
+    };
+
+    compound([
+        (
+            "Inventory",
+            Value::List(vec![
+                item(0, "minecraft:stone", 64),
+                item(1, "minecraft:dirt", 3),
+                item(2, "minecraft:stone", 1),
+            ]),
+        ),
+        (
+            "Pos",
+            Value::List(vec![Value::Double(1.5), Value::Double(64.0)]),
+        ),
+        ("UUID", Value::IntArray(vec![1, -2, 3, -4])),
+        ("Seen", Value::LongArray(vec![i64::MIN, 0])),
+        ("Light", Value::ByteArray(vec![0, 15, 255])),
+    ])
+}
+
+#[test]
+fn value_round_trips_with_array_tags() {
+    let value = inventory();
+    let bytes = write_test_struct(&value);
+    assert_eq!(from_bytes::<Value>(&bytes).unwrap(), value);
+
+    let mut reader = NbtReader::from_slice(&bytes);
+    let mut tags = Vec::new();
+    while let Some(event) = reader.next().unwrap() {
+        if let Event::Field(tag, name) = event {
+            tags.push((name.to_string(), tag));
+        }
+    }
+    assert!(tags.contains(&("UUID".to_string(), Tag::IntArray)));
+    assert!(tags.contains(&("Seen".to_string(), Tag::LongArray)));
+    assert!(tags.contains(&("Light".to_string(), Tag::ByteArray)));
+}
+
+#[test]
+fn nbt_path_gets_values() {
+    let value = inventory();
+    let get = |path: &str| NbtPath::parse(path).unwrap().get(&value).cloned();
+
+    assert_eq!(get("Inventory[1].Count").unwrap(), Value::Byte(3));
+    assert_eq!(get("Inventory[-1].Slot").unwrap(), Value::Byte(2));
+    assert_eq!(
+        get("Inventory[{Slot:1b}].id").unwrap(),
+        Value::String("minecraft:dirt".into())
+    );
+    assert_eq!(get("Pos[0]").unwrap(), Value::Double(1.5));
+    assert_eq!(
+        get("{Light:[B;0b,15b,-1b]}.UUID").unwrap(),
+        Value::IntArray(vec![1, -2, 3, -4])
+    );
+
+    let stone = NbtPath::parse(r#"Inventory[{id:"minecraft:stone"}].Count"#).unwrap();
+    assert_eq!(
+        stone.get_all(&value).unwrap(),
+        [&Value::Byte(64), &Value::Byte(1)]
+    );
+    assert_eq!(
+        NbtPath::parse("Inventory[].Slot")
+            .unwrap()
+            .get_all(&value)
+            .unwrap()
+            .len(),
+        3
+    );
+
+    let err = get("Inventory[{Slot:5b}].id").unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::NoMatch(path) if path == "Inventory[{Slot:5b}]"));
+    let err = get("Pos[2]").unwrap_err();
+    assert_eq!(err.to_string(), "nothing matches `Pos[2]`");
+}
+
+#[test]
+fn nbt_path_modifies_values() {
+    let mut value = inventory();
+
+    let path = NbtPath::parse(r#"Inventory[{id:"minecraft:stone"}].Count"#).unwrap();
+    assert_eq!(path.set(&mut value, Value::Byte(10)).unwrap(), 2);
+    assert_eq!(
+        path.get_all(&value).unwrap(),
+        [&Value::Byte(10), &Value::Byte(10)]
+    );
+
+    // missing compounds on the way are created
+    let name = NbtPath::parse("Inventory[0].tag.display.Name").unwrap();
+    name.set(&mut value, Value::String("Rock".into())).unwrap();
+    assert_eq!(name.get(&value).unwrap(), &Value::String("Rock".into()));
+
+    // a set that fails or matches nothing creates nothing
+    let before = value.clone();
+    for path in ["a.b[0]", "a.b{c:1b}", "Inventory[].tag.x[0]"] {
+        let err = NbtPath::parse(path)
+            .unwrap()
+            .set(&mut value, Value::Int(1))
+            .unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::NoMatch(_)), "{}", path);
+    }
+    let err = NbtPath::parse("Pos[0]")
+        .unwrap()
+        .set(&mut value, Value::Int(1))
+        .unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::IncompatibleListType { .. }));
+    assert_eq!(value, before);
+
+    let pos = NbtPath::parse("Pos").unwrap();
+    pos.insert(&mut value, -1, Value::Double(3.0)).unwrap();
+    pos.insert(&mut value, 0, Value::Double(0.0)).unwrap();
+    assert_eq!(
+        pos.get(&value).unwrap().as_list().unwrap(),
+        [
+            Value::Double(0.0),
+            Value::Double(1.5),
+            Value::Double(64.0),
+            Value::Double(3.0)
+        ]
+    );
+    let err = pos.insert(&mut value, 0, Value::Int(1)).unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::IncompatibleListType {
+            expected: Tag::Double,
+            found: Tag::Int
+        }
+    ));
+
+    let dirt = NbtPath::parse("Inventory[{Slot:1b}]").unwrap();
+    assert_eq!(dirt.remove(&mut value).unwrap(), 1);
+    assert!(dirt.get(&value).is_err());
+    assert_eq!(
+        NbtPath::parse("UUID").unwrap().remove(&mut value).unwrap(),
+        1
+    );
+    assert!(matches!(
+        NbtPath::parse("UUID")
+            .unwrap()
+            .remove(&mut value)
+            .unwrap_err()
+            .kind(),
+        ErrorKind::NoMatch(_)
+    ));
+}
+
+#[test]
+fn nbt_path_reports_invalid_segments() {
+    for (path, segment) in [
+        ("Inventory[x].id", "[x]"),
+        ("Inventory.", "."),
+        ("Inventory[{Slot:}]", "[{Slot:}]"),
+        ("a.b[1", "[1"),
+        ("{Slot:1b}Name", "Name"),
+    ] {
+        let err = NbtPath::parse(path).unwrap_err();
+        assert!(
+            matches!(err.kind(), ErrorKind::InvalidPath(s) if s == segment),
+            "{}: {}",
+            path,
+            err
+        );
+    }
+}
+
+fn canonical<T: Serialize>(value: &T) -> Vec<u8> {
+    let mut output = Vec::new();
+    Serializer::new(&mut output)
+        .with_canonical()
+        .serialize_named("", value)
+        .unwrap();
+    output
+}
+
+#[test]
+fn canonical_mode_sorts_keys_and_normalizes_floats() {
+    #[derive(Serialize)]
+    struct Unsorted {
+        zeta: f32,
+        alpha: Vec<std::collections::HashMap<String, i32>>,
+        mid: f64,
+    }
+
+    #[derive(Serialize)]
+    struct Sorted {
+        alpha: Vec<std::collections::BTreeMap<String, i32>>,
+        mid: f64,
+        zeta: f32,
+    }
+
+    let map: std::collections::HashMap<_, _> = (0..32).map(|i| (format!("key{}", i), i)).collect();
+    let unsorted = Unsorted {
+        zeta: -0.0,
+        alpha: vec![map.clone(), map.clone()],
+        mid: -f64::NAN,
+    };
+    let sorted = Sorted {
+        alpha: vec![map.clone().into_iter().collect(); 2],
+        mid: f64::NAN,
+        zeta: 0.0,
+    };
+    assert_eq!(canonical(&unsorted), canonical(&sorted));
+
+    // the same holds for plain serialization of sorted input
+    let mut plain = Vec::new();
+    to_writer(&mut plain, &sorted, "").unwrap();
+    assert_eq!(canonical(&unsorted), plain);
+
+    // values built in a different order still hash the same
+    let value = inventory();
+    let mut shuffled = Compound::new();
+    let mut entries: Vec<_> = value.as_compound().unwrap().iter().collect();
+    entries.reverse();
+    for (key, entry) in entries {
+        shuffled.insert(key.clone(), entry.clone());
+    }
+    assert_eq!(canonical(&value), canonical(&Value::Compound(shuffled)));
+}
+
+#[cfg(feature = "indexmap")]
+#[test]
+fn ordered_compounds_round_trip_byte_for_byte() {
+    #[derive(Serialize)]
+    struct Entity {
+        z: i32,
+        id: String,
+        a: f32,
+    }
+
+    #[derive(Serialize)]
+    struct Level {
+        zeta: i64,
+        entities: Vec<Entity>,
+        alpha: String,
+        mid: Vec<i32>,
+    }
+
+    let level = Level {
+        zeta: 7,
+        entities: vec![Entity {
+            z: 1,
+            id: "minecraft:pig".to_string(),
+            a: 0.5,
+        }],
+        alpha: "x".to_string(),
+        mid: vec![1, 2],
+    };
+    let mut bytes = Vec::new();
+    to_writer(&mut bytes, &level, "Level").unwrap();
+
+    let mut value: Value = from_bytes(&bytes).unwrap();
+    let keys: Vec<_> = value.as_compound().unwrap().keys().collect();
+    assert_eq!(keys, ["zeta", "entities", "alpha", "mid"]);
+
+    let write = |value: &Value| {
+        let mut output = Vec::new();
+        to_writer(&mut output, value, "Level").unwrap();
+        output
+    };
+    assert_eq!(write(&value), bytes);
+
+    // overwriting an entry keeps its position, removing one keeps the others in order
+    let path = NbtPath::parse("entities[0].id").unwrap();
+    path.set(&mut value, Value::String("minecraft:cow".into()))
+        .unwrap();
+    path.set(&mut value, Value::String("minecraft:pig".into()))
+        .unwrap();
+    assert_eq!(write(&value), bytes);
+
+    NbtPath::parse("entities")
+        .unwrap()
+        .remove(&mut value)
+        .unwrap();
+    let keys: Vec<_> = value.as_compound().unwrap().keys().collect();
+    assert_eq!(keys, ["zeta", "alpha", "mid"]);
+}
+
+/// Builds a region file holding `chunks`, each given as coordinates, compression id and payload.
+fn region_file(chunks: &[(i32, i32, u8, Vec<u8>)]) -> Vec<u8> {
+    let mut file = vec![0; 8192];
+    for (i, (x, z, compression, data)) in chunks.iter().enumerate() {
+        let index = (x.rem_euclid(32) + z.rem_euclid(32) * 32) as usize * 4;
+        let start = file.len() / 4096;
+        file.extend_from_slice(&(data.len() as u32 + 1).to_be_bytes());
+        file.push(*compression);
+        file.extend_from_slice(data);
+        file.resize(file.len().div_ceil(4096) * 4096, 0);
+
+        let sectors = file.len() / 4096 - start;
+        let location = (start as u32) << 8 | sectors as u32;
+        file[index..index + 4].copy_from_slice(&location.to_be_bytes());
+        file[4096 + index..4096 + index + 4].copy_from_slice(&(1000 + i as u32).to_be_bytes());
+    }
+    file
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct RegionChunk {
+    #[serde(rename = "xPos")]
+    x_pos: i32,
+    #[serde(rename = "zPos")]
+    z_pos: i32,
+    blocks: Vec<i64>,
+}
+
+fn region_chunk(x: i32, z: i32) -> (RegionChunk, Vec<u8>) {
+    let chunk = RegionChunk {
+        x_pos: x,
+        z_pos: z,
+        blocks: (0..2000).collect(),
+    };
+    let mut nbt = Vec::new();
+    to_writer(&mut nbt, &chunk, "").unwrap();
+    (chunk, nbt)
+}
+
+#[test]
+fn region_reader_reads_compressed_chunks() {
+    use flate2::write::{GzEncoder, ZlibEncoder};
+    use std::io::Write;
+
+    let (gzip_chunk, nbt) = region_chunk(33, -2);
+    let mut gzip = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gzip.write_all(&nbt).unwrap();
+    let gzip = gzip.finish().unwrap();
+
+    let (zlib_chunk, nbt) = region_chunk(0, 0);
+    let mut zlib = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    zlib.write_all(&nbt).unwrap();
+    let zlib = zlib.finish().unwrap();
+
+    // more than one sector
+    let (plain_chunk, plain) = region_chunk(31, 31);
+
+    let file = region_file(&[
+        (33, -2, 1, gzip.clone()),
+        (0, 0, 2, zlib),
+        (31, 31, 3, plain.clone()),
+    ]);
+    let path = std::env::temp_dir().join(format!("serde_nbt_{}.mca", std::process::id()));
+    std::fs::write(&path, &file).unwrap();
+    let mut region = RegionReader::open(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(region.chunk(1, 30).unwrap(), Some(gzip_chunk));
+    assert_eq!(region.chunk(0, 0).unwrap(), Some(zlib_chunk));
+    assert_eq!(region.chunk::<RegionChunk>(5, 5).unwrap(), None);
+    assert!(region.contains(-1, -1));
+    assert_eq!(region.timestamp(1, 30), Some(1000));
+    assert_eq!(region.timestamp(31, 31), Some(1002));
+    assert_eq!(region.timestamp(5, 5), None);
+
+    assert_eq!(
+        region.raw_chunk(1, 30).unwrap(),
+        Some(RawChunk {
+            compression: Compression::Gzip,
+            data: gzip
+        })
+    );
+    assert_eq!(region.chunk_bytes(31, 31).unwrap(), Some(plain));
+
+    let positions: Vec<_> = region.positions().collect();
+    assert_eq!(positions, [(0, 0), (1, 30), (31, 31)]);
+    let chunks: Vec<_> = region
+        .chunks::<RegionChunk>()
+        .map(|chunk| chunk.unwrap())
+        .collect();
+    assert_eq!(chunks[2], (31, 31, plain_chunk));
+    assert_eq!(chunks.len(), 3);
+}
+
+#[test]
+fn region_reader_rejects_broken_chunks() {
+    let (_, nbt) = region_chunk(0, 0);
+    let mut file = region_file(&[(0, 0, 9, nbt.clone()), (1, 0, 3, nbt)]);
+    // claim the second chunk is longer than its sectors
+    let start = (u32::from_be_bytes(file[4..8].try_into().unwrap()) >> 8) as usize * 4096;
+    file[start..start + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+
+    let mut region = RegionReader::new(Cursor::new(file)).unwrap();
+    let err = region.chunk::<Value>(0, 0).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::UnknownCompression(9)));
+    assert_eq!(err.offset(), Some(8192));
+    let err = region.raw_chunk(1, 0).unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::InvalidChunkLength(u32::MAX)
+    ));
+
+    let mut empty = RegionReader::new(Cursor::new(Vec::new())).unwrap();
+    assert_eq!(empty.positions().count(), 0);
+    assert!(empty.chunk::<Value>(0, 0).unwrap().is_none());
+}
+
+fn location(file: &[u8], x: usize, z: usize) -> (u32, u32) {
+    let index = (x + z * 32) * 4;
+    let location = u32::from_be_bytes(file[index..index + 4].try_into().unwrap());
+    (location >> 8, location & 0xff)
+}
+
+fn sized_chunk(len: i64) -> RegionChunk {
+    RegionChunk {
+        x_pos: 0,
+        z_pos: 0,
+        blocks: (0..len).collect(),
+    }
+}
+
+#[test]
+fn region_writer_allocates_and_reuses_sectors() {
+    let mut region = RegionWriter::new(Cursor::new(Vec::new()))
+        .unwrap()
+        .with_compression(Compression::None);
+    // 1, 2 and 1 sectors
+    region.write_chunk(0, 0, &sized_chunk(10)).unwrap();
+    region.write_chunk(1, 0, &sized_chunk(600)).unwrap();
+    region.write_chunk(2, 0, &sized_chunk(10)).unwrap();
+    assert!(region.remove_chunk(1, 0).unwrap());
+    assert!(!region.remove_chunk(1, 0).unwrap());
+
+    // fits into the gap left by the removed chunk
+    region.write_chunk(3, 0, &sized_chunk(300)).unwrap();
+    // grows out of its sector and into the end of the file
+    region.write_chunk(0, 0, &sized_chunk(1000)).unwrap();
+    // is rewritten in place while it fits
+    region.write_chunk(0, 0, &sized_chunk(600)).unwrap();
+    assert_eq!(region.reader().chunk(0, 0).unwrap(), Some(sized_chunk(600)));
+
+    let file = region.into_inner().into_inner();
+    assert_eq!(location(&file, 3, 0), (3, 1));
+    assert_eq!(location(&file, 2, 0), (5, 1));
+    assert_eq!(location(&file, 0, 0), (6, 2));
+    assert_eq!(location(&file, 1, 0), (0, 0));
+    assert_eq!(file.len(), 8 * 4096);
+
+    let mut reader = RegionReader::new(Cursor::new(file)).unwrap();
+    assert_eq!(reader.chunk(3, 0).unwrap(), Some(sized_chunk(300)));
+    assert_eq!(reader.chunk(2, 0).unwrap(), Some(sized_chunk(10)));
+    assert!(reader.timestamp(2, 0).unwrap() > 1_600_000_000);
+    assert_eq!(reader.chunk::<RegionChunk>(1, 0).unwrap(), None);
+}
+
+#[test]
+fn region_writer_compacts_files() {
+    let path = std::env::temp_dir().join(format!("serde_nbt_compact_{}.mca", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let mut region = RegionWriter::open(&path).unwrap();
+    for x in 0..8 {
+        region.write_chunk(x, 5, &region_chunk(x, 5).0).unwrap();
+    }
+    let timestamp = region.reader().timestamp(6, 5);
+    for x in [0, 2, 3, 5] {
+        region.remove_chunk(x, 5).unwrap();
+    }
+    // too large for any gap, so it ends up at the end
+    region.write_chunk(1, 5, &sized_chunk(20_000)).unwrap();
+
+    let len = region.compact().unwrap();
+    drop(region);
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+
+    let bytes = std::fs::read(&path).unwrap();
+    let mut reader = RegionReader::open(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        reader.positions().collect::<Vec<_>>(),
+        [(1, 5), (4, 5), (6, 5), (7, 5)]
+    );
+    assert_eq!(reader.chunk(1, 5).unwrap(), Some(sized_chunk(20_000)));
+    assert_eq!(reader.chunk(7, 5).unwrap(), Some(region_chunk(7, 5).0));
+    assert_eq!(reader.timestamp(6, 5), timestamp);
+
+    // no gaps left: every sector after the header belongs to exactly one chunk
+    let mut chunks: Vec<_> = [1, 4, 6, 7].map(|x| location(&bytes, x, 5)).to_vec();
+    chunks.sort();
+    let mut next = 2;
+    for (start, sectors) in chunks {
+        assert_eq!(start, next);
+        next += sectors;
+    }
+    assert_eq!(u64::from(next) * 4096, len);
+}
+
+#[test]
+fn region_writer_rejects_oversized_chunks() {
+    let mut region = RegionWriter::new(Cursor::new(Vec::new())).unwrap();
+    let chunk = RawChunk {
+        compression: Compression::None,
+        data: vec![0; 255 * 4096],
+    };
+    let err = region.write_raw_chunk(0, 0, &chunk).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::ChunkTooLarge(len) if *len == 255 * 4096));
+    assert!(!region.reader().contains(0, 0));
+}
+
+#[test]
+fn region_chunks_round_trip_with_lz4() {
+    let mut region = RegionWriter::new(Cursor::new(Vec::new()))
+        .unwrap()
+        .with_compression(Compression::Lz4);
+    // several 64 KiB blocks
+    region.write_chunk(4, 4, &sized_chunk(30_000)).unwrap();
+    region.write_chunk(5, 4, &sized_chunk(0)).unwrap();
+
+    let reader = region.reader();
+    assert_eq!(reader.chunk(4, 4).unwrap(), Some(sized_chunk(30_000)));
+    assert_eq!(reader.chunk(5, 4).unwrap(), Some(sized_chunk(0)));
+
+    let raw = reader.raw_chunk(4, 4).unwrap().unwrap();
+    assert_eq!(raw.compression, Compression::Lz4);
+    assert!(raw.data.starts_with(b"LZ4Block"));
+    assert!(raw.data.len() < 30_000 * 8);
+    // the stream ends with an empty block
+    assert_eq!(raw.data[raw.data.len() - 21..][..8], *b"LZ4Block");
+    assert_eq!(raw.data[raw.data.len() - 12..], [0; 12]);
+
+    let mut corrupt = raw.clone();
+    let last = corrupt.data.len() - 30;
+    corrupt.data[last] ^= 0xff;
+    assert!(matches!(
+        corrupt.decompress().unwrap_err().kind(),
+        ErrorKind::Io(e) if e.kind() == std::io::ErrorKind::InvalidData
+    ));
+}
+
+#[test]
+fn region_chunks_over_a_mebibyte_go_to_mcc_files() {
+    let dir = std::env::temp_dir().join(format!("serde_nbt_mcc_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("r.1.-1.mca");
+    let mcc = dir.join("c.35.-30.mcc");
+
+    // 1.6 MB uncompressed
+    let large = sized_chunk(200_000);
+    let mut region = RegionWriter::open(&path)
+        .unwrap()
+        .with_compression(Compression::None);
+    region.write_chunk(3, 2, &large).unwrap();
+    region.write_chunk(4, 2, &sized_chunk(10)).unwrap();
+    assert!(mcc.exists());
+    drop(region);
+    // only a single sector for each chunk
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 4 * 4096);
+
+    let mut reader = RegionReader::open(&path).unwrap();
+    assert_eq!(reader.chunk(35, -30).unwrap(), Some(large));
+    assert_eq!(reader.chunk(4, 2).unwrap(), Some(sized_chunk(10)));
+
+    // without the path the file can't be found
+    let file = reader.into_inner().into_inner();
+    let mut reader = RegionReader::new(file).unwrap();
+    let err = reader.chunk::<Value>(3, 2).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::UnknownRegionPath));
+
+    // shrinking the chunk moves it back into the region
+    let mut region = RegionWriter::open(&path).unwrap();
+    region.write_chunk(3, 2, &sized_chunk(10)).unwrap();
+    assert!(!mcc.exists());
+    assert_eq!(region.reader().chunk(3, 2).unwrap(), Some(sized_chunk(10)));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn region_reader_names_custom_compression() {
+    let mut data = vec![0, 10];
+    data.extend_from_slice(b"mymod:zstd");
+    data.extend_from_slice(&[1, 2, 3]);
+
+    let mut region = RegionReader::new(Cursor::new(region_file(&[(0, 0, 127, data)]))).unwrap();
+    let err = region.chunk::<Value>(0, 0).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::UnsupportedCompression(name) if name == "mymod:zstd"));
+    assert_eq!(
+        err.to_string(),
+        "unsupported custom chunk compression `mymod:zstd` (offset 8192)"
+    );
+}
+
+#[test]
+fn world_finds_dimensions_and_iterates_chunks() {
+    let dir = std::env::temp_dir().join(format!("serde_nbt_world_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let write = |folder: &str, region: (i32, i32), chunks: &[(i32, i32)]| {
+        let folder = dir.join(folder);
+        std::fs::create_dir_all(&folder).unwrap();
+        let path = folder.join(format!("r.{}.{}.mca", region.0, region.1));
+        let mut writer = RegionWriter::open(path).unwrap();
+        for &(x, z) in chunks {
+            writer.write_chunk(x, z, &region_chunk(x, z).0).unwrap();
+        }
+    };
+    write("region", (0, 0), &[(0, 0), (5, 31)]);
+    write("region", (-1, 0), &[(-1, 3)]);
+    write("entities", (0, 0), &[(7, 7)]);
+    write("DIM-1/region", (0, -1), &[(2, -2)]);
+    write("dimensions/mypack/sky/deep/region", (1, 1), &[(40, 40)]);
+    std::fs::create_dir_all(dir.join("dimensions/mypack/empty")).unwrap();
+    std::fs::write(dir.join("region/notes.txt"), "not a region").unwrap();
+
+    let world = World::open(&dir).unwrap();
+    let ids: Vec<_> = world.dimensions().iter().map(|d| d.id.as_str()).collect();
+    assert_eq!(
+        ids,
+        [
+            "minecraft:overworld",
+            "minecraft:the_nether",
+            "mypack:sky/deep"
+        ]
+    );
+    assert_eq!(world.dimension("minecraft:the_end"), None);
+    assert_eq!(
+        world.dimensions()[0].region_files(Storage::Poi).unwrap(),
+        []
+    );
+
+    let chunks: Vec<_> = world
+        .chunks::<RegionChunk>(Storage::Region)
+        .unwrap()
+        .map(|chunk| {
+            let (dimension, x, z, chunk) = chunk.unwrap();
+            assert_eq!((chunk.x_pos, chunk.z_pos), (x, z));
+            (dimension.id.clone(), x, z)
+        })
+        .collect();
+    let expected = [
+        ("minecraft:overworld", -1, 3),
+        ("minecraft:overworld", 0, 0),
+        ("minecraft:overworld", 5, 31),
+        ("minecraft:the_nether", 2, -2),
+        ("mypack:sky/deep", 40, 40),
+    ]
+    .map(|(d, x, z)| (d.to_string(), x, z));
+    assert_eq!(chunks, expected);
+
+    let entities: Vec<_> = world
+        .chunks::<Value>(Storage::Entities)
+        .unwrap()
+        .map(|chunk| chunk.map(|(_, x, z, _)| (x, z)).unwrap())
+        .collect();
+    assert_eq!(entities, [(7, 7)]);
+
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::iter::ParallelIterator;
+
+        let mut parallel: Vec<_> = world
+            .par_chunks::<RegionChunk>(Storage::Region)
+            .unwrap()
+            .map(|chunk| chunk.map(|(d, x, z, _)| (d.id.clone(), x, z)).unwrap())
+            .collect();
+        parallel.sort();
+        let mut expected = expected.to_vec();
+        expected.sort();
+        assert_eq!(parallel, expected);
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(World::open(&dir).is_err());
+}
+
+#[test]
+fn packed_arrays_use_both_layouts() {
+    let values: Vec<u32> = (0..21).map(|i| if i == 12 { 31 } else { i }).collect();
+
+    let aligned = PackedArray::from_values(&values, 5, Packing::Aligned);
+    let longs = aligned.clone().into_long_array();
+    assert_eq!(longs.len(), 2);
+    // 12 entries fit into the first long, the top 4 bits stay unused
+    assert_eq!(longs[0] as u64 >> 60, 0);
+    assert_eq!(longs[1] & 0x1f, 31);
+
+    let spanning = aligned.repack(5, Packing::Spanning);
+    let longs = spanning.clone().into_long_array();
+    assert_eq!(longs.len(), 2);
+    // entry 12 starts at bit 60 and ends in the second long
+    assert_eq!(longs[0] as u64 >> 60, 0xf);
+    assert_eq!(longs[1] & 1, 1);
+    assert_eq!(spanning.iter().collect::<Vec<_>>(), values);
+
+    let mut widened = spanning.repack(9, Packing::Aligned);
+    assert_eq!(widened.bits_per_entry(), 9);
+    widened.set(20, 300);
+    assert_eq!(widened.get(20), Some(300));
+    assert_eq!(widened.get(21), None);
+    assert_eq!(widened.iter().take(20).collect::<Vec<_>>(), values[..20]);
+
+    let read = PackedArray::from_long_array(longs, 21, 5, Packing::Spanning).unwrap();
+    assert_eq!(read, spanning);
+    let err = PackedArray::from_long_array(LongArray(vec![0; 3]), 21, 5, Packing::Spanning);
+    assert!(matches!(
+        err.unwrap_err().kind(),
+        ErrorKind::ListLengthMismatch {
+            expected: 2,
+            found: 3
+        }
+    ));
+}
+
+#[test]
+fn long_arrays_round_trip_as_array_tags() {
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Heightmap {
+        data: LongArray,
+        ids: IntArray,
+        list: Vec<i64>,
+    }
+
+    let packed = PackedArray::from_values(&[1, 2, 3, 256], 9, Packing::Aligned);
+    let value = Heightmap {
+        data: packed.into(),
+        ids: IntArray(vec![1, -1]),
+        list: vec![4],
+    };
+    let bytes = write_test_struct(&value);
+    assert_eq!(from_bytes::<Heightmap>(&bytes).unwrap(), value);
+
+    let mut reader = NbtReader::from_slice(&bytes);
+    let mut tags = Vec::new();
+    while let Some(event) = reader.next().unwrap() {
+        if let Event::Field(tag, name) = event {
+            tags.push((name.to_string(), tag));
+        }
+    }
+    assert!(tags.contains(&("data".to_string(), Tag::LongArray)));
+    assert!(tags.contains(&("ids".to_string(), Tag::IntArray)));
+    assert!(tags.contains(&("list".to_string(), Tag::List)));
+}
+
+#[cfg(feature = "models")]
+#[test]
+fn chunk_model_resolves_blocks_through_palettes() {
+    use serde_nbt::models::chunk::*;
+
+    let stairs = BlockState::new("minecraft:oak_stairs").with_property("facing", "east");
+    let palette = vec![
+        BlockState::new("minecraft:air"),
+        BlockState::new("minecraft:stone"),
+        stairs.clone(),
+    ];
+    let mut indices = vec![1; 4096];
+    indices[..256].fill(0);
+    // x 3, y 15, z 2
+    indices[(15 * 16 + 2) * 16 + 3] = 2;
+    let chunk = Chunk {
+        data_version: 3465,
+        x: -3,
+        y: -4,
+        z: 7,
+        status: "minecraft:full".to_string(),
+        last_update: 100,
+        inhabited_time: 0,
+        sections: vec![
+            Section {
+                y: -4,
+                block_states: Some(BlockStates {
+                    palette: vec![BlockState::new("minecraft:bedrock")],
+                    data: None,
+                }),
+                biomes: None,
+                block_light: None,
+                sky_light: Some(NibbleArray::filled(15)),
+                other: Compound::new(),
+            },
+            Section {
+                y: 0,
+                block_states: Some(BlockStates {
+                    palette,
+                    data: Some(PackedArray::from_values(&indices, 4, Packing::Aligned).into()),
+                }),
+                biomes: Some(Biomes {
+                    palette: vec![
+                        "minecraft:plains".to_string(),
+                        "minecraft:river".to_string(),
+                    ],
+                    data: Some(PackedArray::from_values(&[1; 64], 1, Packing::Aligned).into()),
+                }),
+                block_light: None,
+                sky_light: None,
+                other: [("future".to_string(), Value::Byte(1))]
+                    .into_iter()
+                    .collect(),
+            },
+        ],
+        block_entities: Vec::new(),
+        heightmaps: Default::default(),
+        other: [
+            (
+                "entities".to_string(),
+                Value::List(vec![compound([("id", Value::String("pig".into()))])]),
+            ),
+            ("PostProcessing".to_string(), Value::List(Vec::new())),
+        ]
+        .into_iter()
+        .collect(),
+    };
+
+    let bytes = write_test_struct(&chunk);
+    let read: Chunk = from_bytes(&bytes).unwrap();
+    assert_eq!(read, chunk);
+    assert_eq!(Chunk::from_bytes(&bytes).unwrap(), chunk);
+    assert!(read.is_supported());
+    // unknown entries survive a round trip
+    assert_eq!(write_test_struct(&read).len(), bytes.len());
+
+    let name = |x, y, z| read.block_at(x, y, z).map(|block| block.name.as_str());
+    assert_eq!(name(0, -64, 0), Some("minecraft:bedrock"));
+    assert_eq!(name(15, -49, 15), Some("minecraft:bedrock"));
+    assert_eq!(name(5, 0, 5), Some("minecraft:air"));
+    assert_eq!(name(5, 1, 5), Some("minecraft:stone"));
+    assert_eq!(name(-48 + 3, 15, 112 + 2), Some("minecraft:oak_stairs"));
+    assert_eq!(
+        read.block_at(3, 15, 2).unwrap().property("facing"),
+        Some("east")
+    );
+    assert_eq!(name(0, -20, 0), None);
+    assert_eq!(read.biome_at(0, 10, 0), Some("minecraft:river"));
+
+    let section = read.section(0).unwrap().block_states.as_ref().unwrap();
+    assert_eq!(section.bits_per_entry(), 4);
+    assert_eq!(
+        section.indices().unwrap().iter().collect::<Vec<_>>(),
+        indices
+    );
+    assert_eq!(section.get(16, 0, 0), None);
+
+    // several palette entries without data are malformed
+    let malformed = BlockStates {
+        data: None,
+        ..section.clone()
+    };
+    assert_eq!(malformed.get(0, 0, 0), None);
+    assert!(malformed.indices().is_err());
+
+    // the tags match what the game writes
+    let value: Value = from_bytes(&bytes).unwrap();
+    let get = |path: &str| NbtPath::parse(path).unwrap().get(&value).cloned();
+    assert_eq!(
+        get("sections[1].block_states.data").unwrap().tag(),
+        Tag::LongArray
+    );
+    assert_eq!(
+        get("sections[1].block_states.palette[2].Properties.facing").unwrap(),
+        Value::String("east".to_string())
+    );
+    assert!(get("sections[0].block_states.data").is_err());
+}
+
+#[cfg(feature = "models")]
+#[test]
+fn chunk_model_rejects_chunks_from_before_1_18() {
+    use serde_nbt::models::chunk::Chunk;
+
+    #[derive(Serialize)]
+    struct Level {
+        #[serde(rename = "xPos")]
+        x: i32,
+        #[serde(rename = "zPos")]
+        z: i32,
+        #[serde(rename = "Status")]
+        status: String,
+    }
+    #[derive(Serialize)]
+    struct OldChunk {
+        #[serde(rename = "DataVersion", skip_serializing_if = "Option::is_none")]
+        data_version: Option<i32>,
+        #[serde(rename = "Level")]
+        level: Level,
+    }
+
+    for (data_version, version) in [(Some(2730), 2730), (None, 0)] {
+        let bytes = write_test_struct(&OldChunk {
+            data_version,
+            level: Level {
+                x: 1,
+                z: 2,
+                status: "full".to_string(),
+            },
+        });
+
+        let err = Chunk::from_bytes(&bytes).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::UnsupportedChunkVersion(v) if *v == version));
+        let err = from_bytes::<Chunk>(&bytes).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains(&format!("unsupported chunk version {}", version)));
+    }
+}
+
+#[test]
+fn nibble_arrays_round_trip_as_byte_arrays() {
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    #[serde(rename_all = "PascalCase")]
+    struct Light {
+        block_light: NibbleArray,
+        sky_light: NibbleArray,
+    }
+
+    let mut block_light = NibbleArray::new();
+    block_light.set(0, 0, 0, 15);
+    block_light.set(1, 0, 0, 7);
+    block_light.set(3, 2, 1, 9);
+    block_light.set(15, 15, 15, 4);
+    let light = Light {
+        block_light,
+        sky_light: NibbleArray::filled(15),
+    };
+
+    assert_eq!(light.block_light.get(0, 0, 0), 15);
+    assert_eq!(light.block_light.get(1, 0, 0), 7);
+    assert_eq!(light.block_light.get(3, 2, 1), 9);
+    assert_eq!(light.block_light.get(2, 2, 1), 0);
+    assert_eq!(light.sky_light.get(8, 8, 8), 15);
+    // YZX order, the first of each pair in the low nibble
+    let bytes = light.block_light.as_bytes();
+    assert_eq!(bytes[0], 0x7f);
+    assert_eq!(bytes[(2 * 16 + 1) * 8 + 1], 0x90);
+    assert_eq!(bytes[2047], 0x40);
+
+    let nbt = write_test_struct(&light);
+    assert_eq!(from_bytes::<Light>(&nbt).unwrap(), light);
+    let value: Value = from_bytes(&nbt).unwrap();
+    let get = |path: &str| NbtPath::parse(path).unwrap().get(&value).cloned().unwrap();
+    assert_eq!(
+        get("BlockLight"),
+        Value::ByteArray(light.block_light.into_bytes())
+    );
+    assert_eq!(get("SkyLight"), Value::ByteArray(vec![0xff; 2048]));
+
+    let short = write_test_struct(&Value::Compound(
+        [("BlockLight".to_string(), Value::ByteArray(vec![0; 16]))]
+            .into_iter()
+            .chain([("SkyLight".to_string(), Value::ByteArray(vec![0; 2048]))])
+            .collect(),
+    ));
+    assert!(from_bytes::<Light>(&short).is_err());
+    // an endless sequence is rejected once it is too long
+    let endless = serde::de::value::SeqDeserializer::<_, serde::de::value::Error>::new(
+        std::iter::repeat(0i8),
+    );
+    assert!(NibbleArray::deserialize(endless).is_err());
+    assert!(matches!(
+        NibbleArray::try_from(vec![0; 2049]).unwrap_err().kind(),
+        ErrorKind::ListLengthMismatch {
+            expected: 2048,
+            found: 2049
+        }
+    ));
+}
+
+#[cfg(feature = "models")]
+#[test]
+fn level_and_player_models_keep_unknown_fields() {
+    use serde_nbt::models::{level::Level, player::Player};
+
+    let string = |s: &str| Value::String(s.to_string());
+    let player = compound([
+        ("DataVersion", Value::Int(3955)),
+        ("UUID", Value::IntArray(vec![1, 2, 3, 4])),
+        ("Dimension", string("minecraft:the_nether")),
+        (
+            "Pos",
+            Value::List(vec![
+                Value::Double(1.5),
+                Value::Double(64.0),
+                Value::Double(-3.5),
+            ]),
+        ),
+        (
+            "Rotation",
+            Value::List(vec![Value::Float(90.0), Value::Float(-10.0)]),
+        ),
+        ("Health", Value::Float(20.0)),
+        ("foodLevel", Value::Int(18)),
+        ("XpLevel", Value::Int(5)),
+        ("playerGameType", Value::Int(1)),
+        (
+            "Inventory",
+            Value::List(vec![compound([
+                ("Slot", Value::Byte(0)),
+                ("id", string("minecraft:diamond_sword")),
+                ("count", Value::Int(1)),
+                (
+                    "components",
+                    compound([("minecraft:damage", Value::Int(3))]),
+                ),
+            ])]),
+        ),
+        (
+            "abilities",
+            compound([
+                ("flying", Value::Byte(1)),
+                ("mayfly", Value::Byte(1)),
+                ("instabuild", Value::Byte(1)),
+                ("invulnerable", Value::Byte(1)),
+                ("mayBuild", Value::Byte(1)),
+                ("flySpeed", Value::Float(0.05)),
+                ("walkSpeed", Value::Float(0.1)),
+            ]),
+        ),
+        (
+            "attributes",
+            Value::List(vec![compound([
+                ("id", string("minecraft:max_health")),
+                ("base", Value::Double(20.0)),
+                ("modifiers", Value::List(Vec::new())),
+            ])]),
+        ),
+        (
+            "recipeBook",
+            compound([("isFurnaceGuiOpen", Value::Byte(0))]),
+        ),
+        ("Brain", compound([("memories", compound([]))])),
+    ]);
+    let level = compound([(
+        "Data",
+        compound([
+            ("DataVersion", Value::Int(3955)),
+            ("LevelName", string("New World")),
+            (
+                "Version",
+                compound([
+                    ("Id", Value::Int(3955)),
+                    ("Name", string("1.21.1")),
+                    ("Series", string("main")),
+                    ("Snapshot", Value::Byte(0)),
+                ]),
+            ),
+            ("version", Value::Int(19133)),
+            ("hardcore", Value::Byte(0)),
+            ("allowCommands", Value::Byte(1)),
+            ("SpawnX", Value::Int(-16)),
+            ("SpawnY", Value::Int(70)),
+            ("SpawnZ", Value::Int(48)),
+            ("Time", Value::Long(123456)),
+            ("raining", Value::Byte(1)),
+            (
+                "GameRules",
+                compound([
+                    ("doDaylightCycle", string("false")),
+                    ("randomTickSpeed", string("3")),
+                ]),
+            ),
+            (
+                "WorldGenSettings",
+                compound([
+                    ("seed", Value::Long(-42)),
+                    ("generate_features", Value::Byte(1)),
+                    ("bonus_chest", Value::Byte(0)),
+                    ("dimensions", compound([])),
+                ]),
+            ),
+            ("Player", player.clone()),
+            ("DragonFight", compound([("DragonKilled", Value::Byte(1))])),
+            ("ServerBrands", Value::List(vec![string("vanilla")])),
+            ("CustomBossEvents", compound([])),
+        ]),
+    )]);
+
+    let dir = std::env::temp_dir().join(format!("serde_nbt_level_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let level_path = dir.join("level.dat");
+    let player_path = dir.join("player.dat");
+    let file = std::fs::File::create(&level_path).unwrap();
+    to_gzip_writer(file, &level, "").unwrap();
+    // uncompressed files load as well
+    std::fs::write(&player_path, write_test_struct(&player)).unwrap();
+
+    let mut loaded = Level::load(&level_path).unwrap();
+    assert_eq!(loaded.name.as_deref(), Some("New World"));
+    assert_eq!(loaded.version.as_ref().unwrap().name, "1.21.1");
+    assert_eq!(loaded.spawn(), [-16, 70, 48]);
+    assert_eq!(loaded.allow_commands, Some(true));
+    assert_eq!((loaded.raining, loaded.thundering), (Some(true), None));
+    assert_eq!(loaded.hardcore, Some(false));
+    assert_eq!(loaded.game_rule("randomTickSpeed"), Some("3"));
+    assert_eq!(loaded.world_gen_settings.as_ref().unwrap().seed, -42);
+    assert!(loaded.other.contains_key("DragonFight"));
+    assert!(!loaded.other.contains_key("LevelName"));
+
+    let singleplayer = loaded.player.as_ref().unwrap();
+    assert_eq!(singleplayer.position, Some([1.5, 64.0, -3.5]));
+    assert_eq!(singleplayer.rotation, Some([90.0, -10.0]));
+    assert_eq!(singleplayer.uuid.as_deref(), Some(&vec![1, 2, 3, 4]));
+    assert_eq!(singleplayer.item(0).unwrap().id, "minecraft:diamond_sword");
+    assert_eq!(singleplayer.abilities.as_ref().unwrap().flying, Some(true));
+    assert_eq!(singleplayer.attributes[0].base, 20.0);
+    assert!(singleplayer.other.contains_key("recipeBook"));
+
+    // saving writes back what was read, with the changes
+    loaded.name = Some("Renamed".to_string());
+    loaded.save(&level_path).unwrap();
+    let saved: Value = from_gzip_reader(std::fs::File::open(&level_path).unwrap()).unwrap();
+    let data = level.as_compound().unwrap()["Data"].as_compound().unwrap();
+    let saved_data = saved.as_compound().unwrap()["Data"].as_compound().unwrap();
+    assert_eq!(saved_data["LevelName"], string("Renamed"));
+    // fields that were missing stay missing
+    assert_eq!(saved_data.len(), data.len());
+    for (key, value) in data {
+        if key != "Player" && key != "LevelName" {
+            assert_eq!(saved_data.get(key), Some(value), "{}", key);
+        }
+    }
+    let saved_player = saved_data["Player"].as_compound().unwrap();
+    assert_eq!(saved_player.len(), player.as_compound().unwrap().len());
+    for (key, value) in player.as_compound().unwrap() {
+        assert_eq!(saved_player.get(key), Some(value), "{}", key);
+    }
+
+    let mut loaded = Player::load(&player_path).unwrap();
+    assert_eq!(loaded.dimension.as_deref(), Some("minecraft:the_nether"));
+    loaded.xp_level = Some(6);
+    loaded.save(&player_path).unwrap();
+    let bytes = std::fs::read(&player_path).unwrap();
+    assert_eq!(bytes[..2], [0x1f, 0x8b]);
+    let reloaded = Player::load(&player_path).unwrap();
+    assert_eq!(reloaded.xp_level, Some(6));
+    assert_eq!(reloaded.other, loaded.other);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "models")]
+#[test]
+fn structure_templates_build_and_convert_palettes() {
+    use serde_nbt::models::{chunk::BlockState, structure::Structure};
+
+    let stone = BlockState::new("minecraft:stone");
+    let chest = BlockState::new("minecraft:chest").with_property("facing", "north");
+    let loot = match compound([("LootTable", Value::String("minecraft:a".into()))]) {
+        Value::Compound(loot) => loot,
+        _ => unreachable!(),
+    };
+    let structure = Structure::new(3955, [1, 1, 1])
+        .with_block([0, 0, 0], stone.clone())
+        .with_block([1, 0, 0], stone.clone())
+        .with_block_entity([0, 1, 2], chest.clone(), loot.clone())
+        .with_entity([0.5, 1.0, 0.5], Compound::new());
+
+    assert_eq!(structure.size, [2, 2, 3]);
+    assert_eq!(
+        structure.palette(0).unwrap(),
+        [stone.clone(), chest.clone()]
+    );
+    assert_eq!(structure.block_at([1, 0, 0]), Some(&stone));
+    assert_eq!(structure.block_at([0, 1, 2]), Some(&chest));
+    assert_eq!(structure.block([0, 1, 2]).unwrap().nbt, Some(loot));
+    assert_eq!(structure.block_at([1, 1, 1]), None);
+    assert_eq!(structure.entities[0].block_pos, [0, 1, 0]);
+
+    // the file layout matches the game's
+    let bytes = write_test_struct(&structure);
+    let value: Value = from_bytes(&bytes).unwrap();
+    let get = |path: &str| NbtPath::parse(path).unwrap().get(&value).cloned().unwrap();
+    assert_eq!(
+        get("size"),
+        Value::List(vec![Value::Int(2), Value::Int(2), Value::Int(3)])
+    );
+    assert_eq!(get("blocks[2].state"), Value::Int(1));
+    assert_eq!(
+        get("palette[1].Properties.facing"),
+        Value::String("north".into())
+    );
+    assert!(NbtPath::parse("palettes").unwrap().get(&value).is_err());
+    assert_eq!(from_bytes::<Structure>(&bytes).unwrap(), structure);
+
+    // a second palette variant, e.g. a rotten and a fresh shipwreck
+    let mut multi = structure.clone().into_multi_palette();
+    assert!(multi.is_multi_palette());
+    let mossy = BlockState::new("minecraft:mossy_cobblestone");
+    let variant = vec![mossy.clone(), chest.clone()];
+    multi.palettes.as_mut().unwrap().push(variant);
+    multi
+        .set_block([1, 1, 1], BlockState::new("minecraft:glass"), None)
+        .unwrap();
+    // stone is at index 0 of the first palette only, so it gets a new index
+    multi.set_block([2, 0, 0], stone.clone(), None).unwrap();
+    assert_eq!(multi.block([2, 0, 0]).unwrap().state, 3);
+    assert_eq!(multi.block_in_palette(1, [2, 0, 0]), Some(&stone));
+    // a position out of bounds leaves the structure as it is
+    let before = multi.clone();
+    for pos in [[-1, 0, 0], [0, i32::MAX, 0]] {
+        let error = multi.set_block(pos, stone.clone(), None).unwrap_err();
+        assert!(matches!(error.kind(), ErrorKind::OutOfBounds(p) if *p == pos));
+    }
+    assert_eq!(multi, before);
+    assert_eq!(multi.palette_count(), 2);
+    assert_eq!(multi.block_at([0, 0, 0]), Some(&stone));
+    assert_eq!(multi.block_in_palette(1, [0, 0, 0]), Some(&mossy));
+    assert_eq!(
+        multi.block_in_palette(1, [1, 1, 1]).unwrap().name,
+        "minecraft:glass"
+    );
+
+    let dir = std::env::temp_dir().join(format!("serde_nbt_structure_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("shipwreck.nbt");
+    multi.save(&path).unwrap();
+    let loaded = Structure::load(&path).unwrap();
+    assert_eq!(loaded, multi);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(loaded.clone().into_single_palette(2), Err(loaded.clone()));
+    let single = loaded.into_single_palette(1).unwrap();
+    assert!(!single.is_multi_palette());
+    assert_eq!(single.block_at([0, 0, 0]), Some(&mossy));
+    assert_eq!(single.palette(1), None);
+    assert_eq!(single.clone().into_multi_palette().palette_count(), 1);
+}
+
+#[cfg(feature = "models")]
+#[test]
+fn schematics_convert_between_sponge_and_litematica() {
+    use serde_nbt::models::{
+        chunk::BlockState,
+        schematic::{Schematic, SpongeVersion},
+    };
+
+    assert_eq!(
+        "minecraft:oak_stairs[facing=east,half=top]"
+            .parse::<BlockState>()
+            .unwrap(),
+        BlockState::new("minecraft:oak_stairs")
+            .with_property("half", "top")
+            .with_property("facing", "east")
+    );
+    assert_eq!(
+        BlockState::new("minecraft:lever")
+            .with_property("powered", "true")
+            .to_string(),
+        "minecraft:lever[powered=true]"
+    );
+    assert!("minecraft:lever[powered]".parse::<BlockState>().is_err());
+
+    let chest = BlockState::new("minecraft:chest").with_property("facing", "west");
+    let nbt = match compound([
+        ("id", Value::String("minecraft:chest".into())),
+        ("Items", Value::List(Vec::new())),
+    ]) {
+        Value::Compound(nbt) => nbt,
+        _ => unreachable!(),
+    };
+    // over 128 states, so some of the varints take two bytes
+    let mut schematic =
+        Schematic::new(3955, [10, 4, 7]).with_block_entity([9, 3, 6], chest.clone(), nbt.clone());
+    for i in 0..200 {
+        let pos = [i % 10, i / 70, i / 10 % 7];
+        let state = BlockState::new(format!("minecraft:block_{}", i));
+        schematic.set_block(pos, state);
+    }
+    assert_eq!(schematic.palette.len(), 202);
+
+    let same_blocks = |a: &Schematic, b: &Schematic| {
+        assert_eq!(a.size, b.size);
+        for y in 0..a.size[1] {
+            for z in 0..a.size[2] {
+                for x in 0..a.size[0] {
+                    assert_eq!(a.block_at([x, y, z]), b.block_at([x, y, z]));
+                }
+            }
+        }
+        assert_eq!(a.block_entities, b.block_entities);
+    };
+
+    for version in [SpongeVersion::V2, SpongeVersion::V3] {
+        let mut bytes = Vec::new();
+        schematic.write_sponge(&mut bytes, version).unwrap();
+        let read = Schematic::read_sponge(&bytes[..]).unwrap();
+        same_blocks(&schematic, &read);
+        assert_eq!(read.data_version, 3955);
+    }
+
+    let mut bytes = Vec::new();
+    schematic.write_litematic(&mut bytes, "Test").unwrap();
+    let read = Schematic::read_litematic(&bytes[..]).unwrap();
+    same_blocks(&schematic, &read);
+    assert_eq!(read.palette[0].name, "minecraft:air");
+
+    // Litematica packs states across longs, 8 bits here
+    let file: Value = from_gzip_reader(&bytes[..]).unwrap();
+    let get = |path: &str| NbtPath::parse(path).unwrap().get(&file).cloned().unwrap();
+    assert_eq!(get("Regions.Test.BlockStates").tag(), Tag::LongArray);
+    assert_eq!(
+        get("Regions.Test.Size"),
+        compound([
+            ("x", Value::Int(10)),
+            ("y", Value::Int(4)),
+            ("z", Value::Int(7))
+        ])
+    );
+    assert_eq!(get("Regions.Test.TileEntities[0].x"), Value::Int(9));
+    assert_eq!(get("Metadata.TotalBlocks"), Value::Int(201));
+
+    // converting through files
+    let dir = std::env::temp_dir().join(format!("serde_nbt_schematic_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("house.litematic"), &bytes).unwrap();
+    let house = Schematic::load(dir.join("house.litematic")).unwrap();
+    house.save(dir.join("house.schem")).unwrap();
+    let converted = Schematic::load(dir.join("house.schem")).unwrap();
+    same_blocks(&schematic, &converted);
+    let file: Value =
+        from_gzip_reader(std::fs::File::open(dir.join("house.schem")).unwrap()).unwrap();
+    assert_eq!(
+        NbtPath::parse("Schematic.Version")
+            .unwrap()
+            .get(&file)
+            .unwrap(),
+        &Value::Int(3)
+    );
+    assert_eq!(
+        NbtPath::parse("Schematic.Blocks.BlockEntities[0].Id")
+            .unwrap()
+            .get(&file)
+            .unwrap(),
+        &Value::String("minecraft:chest".into())
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "models")]
+#[test]
+fn schematics_read_litematica_regions_and_reject_bad_data() {
+    use serde_nbt::models::schematic::Schematic;
+
+    let vec3 = |x, y, z| {
+        compound([
+            ("x", Value::Int(x)),
+            ("y", Value::Int(y)),
+            ("z", Value::Int(z)),
+        ])
+    };
+    let state = |name: &str| compound([("Name", Value::String(name.into()))]);
+    // 3 bits per state, so entry 21 spans the first two longs
+    let states: Vec<u32> = (0..24).map(|i| if i == 21 { 4 } else { i % 2 }).collect();
+    let packed = PackedArray::from_values(&states, 3, Packing::Spanning);
+    let region = |position, size| {
+        compound([
+            ("Position", position),
+            ("Size", size),
+            (
+                "BlockStatePalette",
+                Value::List(vec![
+                    state("minecraft:air"),
+                    state("minecraft:stone"),
+                    state("minecraft:dirt"),
+                    state("minecraft:sand"),
+                    state("minecraft:glass"),
+                ]),
+            ),
+            (
+                "BlockStates",
+                Value::LongArray(packed.clone().into_long_array().0),
+            ),
+        ])
+    };
+    let file = compound([
+        ("MinecraftDataVersion", Value::Int(3700)),
+        ("Version", Value::Int(6)),
+        (
+            "Metadata",
+            compound([("Name", Value::String("Two".into()))]),
+        ),
+        (
+            "Regions",
+            compound([
+                ("A", region(vec3(0, 0, 0), vec3(2, 3, 4))),
+                // selected towards negative x, so it covers x from -3 to -2
+                ("B", region(vec3(-2, 0, 0), vec3(-2, 3, 4))),
+            ]),
+        ),
+    ]);
+    let mut bytes = Vec::new();
+    to_gzip_writer(&mut bytes, &file, "").unwrap();
+
+    let schematic = Schematic::read_litematic(&bytes[..]).unwrap();
+    assert_eq!(schematic.size, [5, 3, 4]);
+    assert_eq!(schematic.offset, [-3, 0, 0]);
+    let name = |pos| schematic.block_at(pos).map(|s| s.name.as_str());
+    // index 21 is x 1, y 2, z 2
+    assert_eq!(name([3 + 1, 2, 2]), Some("minecraft:glass"));
+    assert_eq!(name([1, 2, 2]), Some("minecraft:glass"));
+    assert_eq!(name([3 + 1, 0, 0]), Some("minecraft:stone"));
+    assert_eq!(name([2, 0, 0]), Some("minecraft:air"));
+    assert_eq!(name([5, 0, 0]), None);
+
+    // a block data varint that never ends
+    let sponge = compound([
+        ("Version", Value::Int(2)),
+        ("Width", Value::Short(1)),
+        ("Height", Value::Short(1)),
+        ("Length", Value::Short(2)),
+        ("Palette", compound([("minecraft:stone", Value::Int(0))])),
+        ("BlockData", Value::ByteArray(vec![0, 0x80])),
+    ]);
+    let mut bytes = Vec::new();
+    to_gzip_writer(&mut bytes, &sponge, "Schematic").unwrap();
+    let err = Schematic::read_sponge(&bytes[..]).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::InvalidSchematic(_)));
+
+    let mut bytes = Vec::new();
+    to_gzip_writer(&mut bytes, &compound([("Version", Value::Int(9))]), "").unwrap();
+    let err = Schematic::read_sponge(&bytes[..]).unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::UnsupportedSchematicVersion(9)
+    ));
+}
+
+#[cfg(feature = "models")]
+#[test]
+fn schematics_check_sizes_and_palettes_before_allocating() {
+    use serde_nbt::models::schematic::Schematic;
+
+    let sponge = |side: i16, palette_id: i32| {
+        let sponge = compound([
+            ("Version", Value::Int(2)),
+            ("Width", Value::Short(side)),
+            ("Height", Value::Short(side)),
+            ("Length", Value::Short(side)),
+            (
+                "Palette",
+                compound([("minecraft:stone", Value::Int(palette_id))]),
+            ),
+            ("BlockData", Value::ByteArray(vec![0])),
+        ]);
+        let mut bytes = Vec::new();
+        to_gzip_writer(&mut bytes, &sponge, "Schematic").unwrap();
+        Schematic::read_sponge(&bytes[..])
+    };
+    let schematic = sponge(1, 0).unwrap();
+    assert_eq!(
+        schematic.block_at([0, 0, 0]).unwrap().name,
+        "minecraft:stone"
+    );
+    // a side of 65535
+    let err = sponge(-1, 0).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::InvalidSchematic(_)));
+    let err = sponge(1, i32::MAX).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::InvalidSchematic(_)));
+
+    // version 3 may leave out the blocks, e.g. for a schematic of only entities
+    let v3 = compound([(
+        "Schematic",
+        compound([
+            ("Version", Value::Int(3)),
+            ("DataVersion", Value::Int(3955)),
+            ("Width", Value::Short(2)),
+            ("Height", Value::Short(3)),
+            ("Length", Value::Short(4)),
+            ("Offset", Value::IntArray(vec![1, 2, 3])),
+        ]),
+    )]);
+    let mut bytes = Vec::new();
+    to_gzip_writer(&mut bytes, &v3, "").unwrap();
+    let schematic = Schematic::read_sponge(&bytes[..]).unwrap();
+    assert_eq!(
+        schematic,
+        Schematic {
+            offset: [1, 2, 3],
+            ..Schematic::new(3955, [2, 3, 4])
+        }
+    );
+
+    let litematic = |position: [i32; 3], size: [i32; 3], longs: usize| {
+        let vec3 = |[x, y, z]: [i32; 3]| {
+            compound([
+                ("x", Value::Int(x)),
+                ("y", Value::Int(y)),
+                ("z", Value::Int(z)),
+            ])
+        };
+        let region = compound([
+            ("Position", vec3(position)),
+            ("Size", vec3(size)),
+            (
+                "BlockStatePalette",
+                Value::List(vec![compound([(
+                    "Name",
+                    Value::String("minecraft:air".into()),
+                )])]),
+            ),
+            ("BlockStates", Value::LongArray(vec![0; longs])),
+        ]);
+        let file = compound([
+            ("MinecraftDataVersion", Value::Int(3700)),
+            ("Version", Value::Int(6)),
+            ("Metadata", compound([])),
+            ("Regions", compound([("A", region)])),
+        ]);
+        let mut bytes = Vec::new();
+        to_gzip_writer(&mut bytes, &file, "").unwrap();
+        Schematic::read_litematic(&bytes[..])
+    };
+    assert_eq!(litematic([0; 3], [4, 4, 4], 2).unwrap().size, [4, 4, 4]);
+    for (position, size) in [
+        ([0; 3], [i32::MIN, 1, 1]),
+        ([i32::MAX, 0, 0], [2, 1, 1]),
+        ([i32::MIN, 0, 0], [-2, 1, 1]),
+        ([0; 3], [i32::MAX; 3]),
+        ([0; 3], [1 << 20, 1 << 20, 1]),
+    ] {
+        let err = litematic(position, size, 2).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::InvalidSchematic(_)));
+    }
+}